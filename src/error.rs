@@ -0,0 +1,127 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io;
+
+/// The errors a command can fail with.
+#[derive(Debug)]
+pub enum TaskError {
+    /// No task has the given ID.
+    NotFound(i32),
+    /// An argument that should be a task ID is not one.
+    InvalidId(String),
+    /// A required argument was not given, described by the rest of the "Missing ..." message.
+    MissingArgument(&'static str),
+    /// A command or an input that cannot be carried out as given, with the message saying why.
+    Invalid(String),
+    /// Reading or writing a file or a stream failed. The message of the error says what failed.
+    Io(io::Error),
+    /// The task file does not hold a valid task list.
+    Parse(serde_json::Error),
+}
+
+impl TaskError {
+    /// Builds an `Io` error whose message starts with what was being done.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - What failed, e.g. "Failed to save tasks to tasks.json".
+    /// * `err` - The underlying I/O error, whose kind is kept.
+    pub fn io(context: impl Display, err: io::Error) -> TaskError {
+        TaskError::Io(io::Error::new(err.kind(), format!("{}: {}", context, err)))
+    }
+}
+
+impl Display for TaskError {
+    /// Formats the `TaskError` as the message printed to the user.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskError::NotFound(id) => write!(f, "No task with id {}", id),
+            TaskError::InvalidId(arg) => write!(f, "Invalid task id: {}", arg),
+            TaskError::MissingArgument(what) => write!(f, "Missing {}", what),
+            TaskError::Invalid(message) => write!(f, "{}", message),
+            TaskError::Io(err) => write!(f, "{}", err),
+            TaskError::Parse(err) => write!(f, "Failed to parse task file: {}", err),
+        }
+    }
+}
+
+impl Error for TaskError {
+    /// Returns the underlying I/O or JSON error, if any.
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TaskError::Io(err) => Some(err),
+            TaskError::Parse(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Errors are equal when they are of the same variant and would print the same message,
+/// since I/O and JSON errors cannot be compared otherwise.
+impl PartialEq for TaskError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (TaskError::NotFound(a), TaskError::NotFound(b)) => a == b,
+            (TaskError::InvalidId(a), TaskError::InvalidId(b)) => a == b,
+            (TaskError::MissingArgument(a), TaskError::MissingArgument(b)) => a == b,
+            (TaskError::Invalid(a), TaskError::Invalid(b)) => a == b,
+            (TaskError::Io(a), TaskError::Io(b)) => {
+                a.kind() == b.kind() && a.to_string() == b.to_string()
+            }
+            (TaskError::Parse(a), TaskError::Parse(b)) => a.to_string() == b.to_string(),
+            _ => false,
+        }
+    }
+}
+
+impl From<String> for TaskError {
+    /// Wraps a message in an `Invalid` error.
+    fn from(message: String) -> Self {
+        TaskError::Invalid(message)
+    }
+}
+
+impl From<serde_json::Error> for TaskError {
+    /// Wraps a JSON error in a `Parse` error.
+    fn from(err: serde_json::Error) -> Self {
+        TaskError::Parse(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages() {
+        assert_eq!(TaskError::NotFound(3).to_string(), "No task with id 3");
+        assert_eq!(
+            TaskError::InvalidId("abc".to_string()).to_string(),
+            "Invalid task id: abc"
+        );
+        assert_eq!(
+            TaskError::MissingArgument("id of task to show").to_string(),
+            "Missing id of task to show"
+        );
+        let err = TaskError::io(
+            "Failed to save tasks to tasks.json",
+            io::Error::from(io::ErrorKind::PermissionDenied),
+        );
+        assert_eq!(
+            err.to_string(),
+            "Failed to save tasks to tasks.json: permission denied"
+        );
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn equality_compares_variants() {
+        assert_eq!(TaskError::NotFound(3), TaskError::NotFound(3));
+        assert_ne!(
+            TaskError::NotFound(3),
+            TaskError::Invalid("No task with id 3".to_string())
+        );
+        let io_error = || TaskError::io("Failed", io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(io_error(), io_error());
+    }
+}
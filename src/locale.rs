@@ -0,0 +1,216 @@
+use crate::error::TaskError;
+use chrono::{TimeDelta, Weekday};
+use std::str::FromStr;
+
+/// The language of the labels and dates meant to be read by people, chosen with `--locale`.
+///
+/// Only the due date groups of `list --group-by due`, the stale marker of `list --stale-after`,
+/// the age of the last update printed by `show` and the weekday names accepted as due dates
+/// depend on it. Every other message is in English whatever the locale.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Locale {
+    #[default]
+    English,
+    French,
+}
+
+/// The full and abbreviated English weekday names, from Monday.
+const ENGLISH_WEEKDAYS: [(&str, &str); 7] = [
+    ("monday", "mon"),
+    ("tuesday", "tue"),
+    ("wednesday", "wed"),
+    ("thursday", "thu"),
+    ("friday", "fri"),
+    ("saturday", "sat"),
+    ("sunday", "sun"),
+];
+
+/// The full and abbreviated French weekday names, from Monday.
+const FRENCH_WEEKDAYS: [(&str, &str); 7] = [
+    ("lundi", "lun"),
+    ("mardi", "mar"),
+    ("mercredi", "mer"),
+    ("jeudi", "jeu"),
+    ("vendredi", "ven"),
+    ("samedi", "sam"),
+    ("dimanche", "dim"),
+];
+
+/// The units a duration is rounded down to when humanized, from the smallest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TimeUnit {
+    Minute,
+    Hour,
+    Day,
+    Month,
+    Year,
+}
+
+impl FromStr for Locale {
+    type Err = TaskError;
+
+    /// Parses a locale from its language code, `en` or `fr`, ignoring a region or an encoding
+    /// following it as in `fr_FR.UTF-8`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let language = s.split(['_', '-', '.']).next().unwrap_or_default();
+        match language.to_lowercase().as_str() {
+            "en" => Ok(Locale::English),
+            "fr" => Ok(Locale::French),
+            _ => Err(TaskError::Invalid(format!("Unknown locale: {}", s))),
+        }
+    }
+}
+
+impl Locale {
+    /// Parses the name of a day of the week, in full or abbreviated, whatever its case.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name to parse, such as `friday`, `Fri` or `vendredi`.
+    ///
+    /// # Returns
+    ///
+    /// The named `Weekday`, or `None` if the name is not one in this locale.
+    pub fn parse_weekday(self, name: &str) -> Option<Weekday> {
+        let names = match self {
+            Locale::English => &ENGLISH_WEEKDAYS,
+            Locale::French => &FRENCH_WEEKDAYS,
+        };
+        let name = name.to_lowercase();
+        let index = names
+            .iter()
+            .position(|(full, short)| name == *full || name == *short)?;
+        Weekday::try_from(index as u8).ok()
+    }
+
+    /// Returns the marker printed before the tasks listed as stale.
+    pub fn stale_marker(self) -> &'static str {
+        match self {
+            Locale::English => "(stale)",
+            Locale::French => "(en sommeil)",
+        }
+    }
+
+    /// Describes a duration relative to now, rounded down to its largest whole unit.
+    ///
+    /// A month counts as 30 days and a year as 365, and less than a minute is "just now".
+    ///
+    /// # Arguments
+    ///
+    /// * `delta` - How long ago something happened, negative if it is yet to happen.
+    ///
+    /// # Returns
+    ///
+    /// The description, such as "3 days ago" or "in 2 hours", as "il y a 3 jours" in French.
+    pub fn humanize(self, delta: TimeDelta) -> String {
+        let seconds = delta.num_seconds().unsigned_abs();
+        let (amount, unit) = match seconds {
+            0..60 => {
+                return match self {
+                    Locale::English => "just now".to_string(),
+                    Locale::French => "à l'instant".to_string(),
+                }
+            }
+            60..3_600 => (seconds / 60, TimeUnit::Minute),
+            3_600..86_400 => (seconds / 3_600, TimeUnit::Hour),
+            _ => match seconds / 86_400 {
+                days @ 0..30 => (days, TimeUnit::Day),
+                days @ 30..365 => (days / 30, TimeUnit::Month),
+                days => (days / 365, TimeUnit::Year),
+            },
+        };
+        let unit = self.unit_name(unit, amount > 1);
+        match (self, delta < TimeDelta::zero()) {
+            (Locale::English, false) => format!("{} {} ago", amount, unit),
+            (Locale::English, true) => format!("in {} {}", amount, unit),
+            (Locale::French, false) => format!("il y a {} {}", amount, unit),
+            (Locale::French, true) => format!("dans {} {}", amount, unit),
+        }
+    }
+
+    /// Returns the name of a unit of time.
+    ///
+    /// # Arguments
+    ///
+    /// * `unit` - The unit to name.
+    /// * `plural` - Whether the name is for more than one of it.
+    fn unit_name(self, unit: TimeUnit, plural: bool) -> &'static str {
+        match (self, unit, plural) {
+            (Locale::English, TimeUnit::Minute, false) => "minute",
+            (Locale::English, TimeUnit::Minute, true) => "minutes",
+            (Locale::English, TimeUnit::Hour, false) => "hour",
+            (Locale::English, TimeUnit::Hour, true) => "hours",
+            (Locale::English, TimeUnit::Day, false) => "day",
+            (Locale::English, TimeUnit::Day, true) => "days",
+            (Locale::English, TimeUnit::Month, false) => "month",
+            (Locale::English, TimeUnit::Month, true) => "months",
+            (Locale::English, TimeUnit::Year, false) => "year",
+            (Locale::English, TimeUnit::Year, true) => "years",
+            (Locale::French, TimeUnit::Minute, false) => "minute",
+            (Locale::French, TimeUnit::Minute, true) => "minutes",
+            (Locale::French, TimeUnit::Hour, false) => "heure",
+            (Locale::French, TimeUnit::Hour, true) => "heures",
+            (Locale::French, TimeUnit::Day, false) => "jour",
+            (Locale::French, TimeUnit::Day, true) => "jours",
+            (Locale::French, TimeUnit::Month, _) => "mois",
+            (Locale::French, TimeUnit::Year, false) => "an",
+            (Locale::French, TimeUnit::Year, true) => "ans",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locale_parsed_from_language_code() {
+        assert_eq!("en".parse(), Ok(Locale::English));
+        assert_eq!("fr".parse(), Ok(Locale::French));
+        assert_eq!("fr_FR.UTF-8".parse(), Ok(Locale::French));
+        assert_eq!("en-GB".parse(), Ok(Locale::English));
+        assert_eq!(
+            "de".parse::<Locale>(),
+            Err(TaskError::Invalid("Unknown locale: de".to_string()))
+        );
+    }
+
+    #[test]
+    fn french_weekdays_parsed() {
+        assert_eq!(Locale::French.parse_weekday("lundi"), Some(Weekday::Mon));
+        assert_eq!(Locale::French.parse_weekday("Vendredi"), Some(Weekday::Fri));
+        assert_eq!(Locale::French.parse_weekday("dim"), Some(Weekday::Sun));
+        assert_eq!(Locale::French.parse_weekday("monday"), None);
+    }
+
+    #[test]
+    fn english_weekdays_parsed() {
+        assert_eq!(Locale::English.parse_weekday("monday"), Some(Weekday::Mon));
+        assert_eq!(Locale::English.parse_weekday("WED"), Some(Weekday::Wed));
+        assert_eq!(Locale::English.parse_weekday("lundi"), None);
+    }
+
+    #[test]
+    fn durations_humanized_in_english() {
+        let english = |delta| Locale::English.humanize(delta);
+        assert_eq!(english(TimeDelta::seconds(30)), "just now");
+        assert_eq!(english(TimeDelta::minutes(1)), "1 minute ago");
+        assert_eq!(english(TimeDelta::hours(5)), "5 hours ago");
+        assert_eq!(english(TimeDelta::days(3)), "3 days ago");
+        assert_eq!(english(TimeDelta::days(65)), "2 months ago");
+        assert_eq!(english(TimeDelta::days(400)), "1 year ago");
+        assert_eq!(english(TimeDelta::days(-2)), "in 2 days");
+    }
+
+    #[test]
+    fn durations_humanized_in_french() {
+        let french = |delta| Locale::French.humanize(delta);
+        assert_eq!(french(TimeDelta::seconds(30)), "à l'instant");
+        assert_eq!(french(TimeDelta::minutes(1)), "il y a 1 minute");
+        assert_eq!(french(TimeDelta::hours(1)), "il y a 1 heure");
+        assert_eq!(french(TimeDelta::days(3)), "il y a 3 jours");
+        assert_eq!(french(TimeDelta::days(30)), "il y a 1 mois");
+        assert_eq!(french(TimeDelta::days(800)), "il y a 2 ans");
+        assert_eq!(french(TimeDelta::hours(-2)), "dans 2 heures");
+    }
+}
@@ -1,367 +1,6409 @@
+use crate::error::TaskError;
+use crate::locale::Locale;
+use crate::task_repository::FileLayout;
+use crate::task_repository::FileVersion;
+use crate::task_repository::Priority;
+use crate::task_repository::Tag;
 use crate::task_repository::Task;
 use crate::task_repository::TaskRepository;
 use crate::task_repository::TaskStatus;
+use chrono::{
+    DateTime, Datelike, Local, NaiveDate, NaiveDateTime, SecondsFormat, TimeDelta, Weekday,
+};
+use serde::{Deserialize, Serialize, Serializer};
+use std::cell::Cell;
+use std::collections::BTreeSet;
 use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufRead, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 use std::string::ToString;
+use std::time::{Duration, Instant, SystemTime};
 
+pub mod error;
+pub mod locale;
 pub mod task_repository;
 
+/// Number of tasks displayed by the "recent" and "recent-done" commands when no count is given.
+const DEFAULT_RECENT_COUNT: usize = 5;
+/// The name of the task file in the home directory, used when no task file is given.
+const DEFAULT_TASK_FILE_NAME: &str = ".tasklist.json";
+/// Number of tasks a bulk deletion removes without asking, when `--confirm-threshold` is not given.
+const DEFAULT_CONFIRM_THRESHOLD: usize = 5;
+
 /// The main entry point of the application.
 ///
-/// This function reads command-line arguments and the `TASK_FILE` environment variable,
-/// loads the task repository, and handles the provided command.
+/// This function runs the application against a buffered stdout, which is flushed once the
+/// command is done. Failing to flush it fails the command, unless stdout was closed early.
 ///
 /// # Returns
 ///
 /// An `ExitCode` indicating the success or failure of the operation.
 fn main() -> ExitCode {
     let args: Vec<String> = env::args().collect();
+    let mut out = BufWriter::new(io::stdout().lock());
+    let code = run(&args, &mut out, &mut io::stderr());
+    exit_after_output(out.flush(), code, &mut io::stderr())
+}
+
+/// Gives the exit code of the application once its last output is written.
+///
+/// As for the output of commands, output closed early by its reader is not an error. Any other
+/// failure to write it is reported to `err` and turns the exit code into a failure.
+///
+/// # Arguments
+///
+/// * `written` - The result of writing the last output.
+/// * `code` - The exit code to give if it was written.
+/// * `err` - The writer receiving the write error, if any.
+///
+/// # Returns
+///
+/// The given `ExitCode`, or a failure if the output could not be written.
+fn exit_after_output(written: io::Result<()>, code: ExitCode, err: &mut impl Write) -> ExitCode {
+    match ignore_broken_pipe(written) {
+        Ok(()) => code,
+        Err(error) => {
+            let _ = writeln!(err, "{}", error);
+            ExitCode::from(1)
+        }
+    }
+}
+
+/// Runs the application, writing all output to `out`.
+///
+/// This function parses the command-line arguments, resolves the task file from the `--file`
+/// option, the `TASK_FILE` environment variable or the home directory, loads the task
+/// repository, and handles the provided command.
+///
+/// # Arguments
+///
+/// * `args` - The command-line arguments, starting with the name of the program.
+/// * `out` - The writer receiving the output of the command.
+/// * `err` - The writer receiving the task file being read and, with `--profile-timing`,
+///   the timings.
+///
+/// # Returns
+///
+/// An `ExitCode` indicating the success or failure of the operation.
+fn run(args: &[String], out: &mut impl Write, err: &mut impl Write) -> ExitCode {
+    let (options, args) = match parse_global_options(args) {
+        Ok(parsed) => parsed,
+        Err(error) => return exit_after_output(writeln!(out, "{}", error), ExitCode::from(1), err),
+    };
     if args.len() < 2 {
-        println!("No command provided, goodbye.");
-        return ExitCode::from(0);
+        return exit_after_output(write!(out, "{}", usage()), ExitCode::from(0), err);
     }
 
-    let task_file = match env::var("TASK_FILE") {
-        Ok(val) => val,
-        Err(_) => {
-            println!("TASK_FILE environment variable not set");
-            return ExitCode::from(1);
+    let env_task_file = env::var_os("TASK_FILE");
+    let is_default = options.task_file.is_none() && env_task_file.is_none();
+    let task_file = match resolve_task_file(options.task_file, env_task_file, env::var_os("HOME")) {
+        Some(task_file) => task_file,
+        None => {
+            let written = writeln!(out, "No task file: use --file or set TASK_FILE or HOME");
+            return exit_after_output(written, ExitCode::from(1), err);
         }
     };
+    let ctx = Context {
+        task_file,
+        create_parent_dirs: is_default || options.ensure_dir,
+        readonly: options.readonly,
+        save_time: Cell::default(),
+        saves: Cell::default(),
+        loaded_version: Cell::default(),
+        locale: options.locale.unwrap_or_default(),
+        confirm_threshold: options
+            .confirm_threshold
+            .unwrap_or(DEFAULT_CONFIRM_THRESHOLD),
+    };
+    let profile_timing = options.profile_timing;
 
-    println!("Reading tasks from {}", task_file);
-    let mut repo = task_repository::load_repository(&task_file);
+    // Kept off `out`, so that the output of `list --json`, `count` or `dump` can be piped.
+    let _ = writeln!(err, "Reading tasks from {}", ctx.task_file.display());
+    let load_start = Instant::now();
+    if args[1..] == ["count"] {
+        // Counting every task needs only their ids, so they are not built.
+        let count = task_repository::count_tasks(&ctx.task_file);
+        if profile_timing {
+            let _ = write_timings(err, load_start.elapsed(), Duration::ZERO, Duration::ZERO);
+        }
+        return match count {
+            Ok(count) => exit_after_output(writeln!(out, "{}", count), ExitCode::from(0), err),
+            Err(error) => exit_after_output(writeln!(out, "{}", error), ExitCode::from(1), err),
+        };
+    }
+    let json_cache = options
+        .json_cache
+        .filter(|_| args[1..] == ["list", "--json"]);
+    if let (Some(cache_file), Ok(source_meta)) = (json_cache, fs::metadata(&ctx.task_file)) {
+        // The tasks are only loaded if the task file changed since the cached listing.
+        let load = || task_repository::load_repository(&ctx.task_file);
+        let json = cached_json(load, &source_meta, &cache_file);
+        if profile_timing {
+            let _ = write_timings(err, load_start.elapsed(), Duration::ZERO, Duration::ZERO);
+        }
+        return match json {
+            Ok(json) => exit_after_output(write!(out, "{}", json), ExitCode::from(0), err),
+            Err(error) => exit_after_output(writeln!(out, "{}", error), ExitCode::from(1), err),
+        };
+    }
+    let loaded = task_repository::load_repository_with_version(&ctx.task_file);
+    let load_time = load_start.elapsed();
+    let mut repo = match loaded {
+        Ok((repo, version)) => {
+            ctx.loaded_version.set(version);
+            repo
+        }
+        Err(error) => return exit_after_output(writeln!(out, "{}", error), ExitCode::from(1), err),
+    };
+    if let Some(layout) = options.layout {
+        repo.set_layout(layout);
+    }
+    repo.set_reuse_ids(options.reuse_ids);
 
-    match handle_command(&args, &mut repo) {
+    let command_start = Instant::now();
+    let result = handle_command(&args, &mut repo, &ctx, out);
+    if ctx.saves.get() > 0 {
+        let _ = writeln!(err, "Saved tasks to {}", ctx.task_file.display());
+    }
+    if profile_timing {
+        let save_time = ctx.save_time.get();
+        let command_time = command_start.elapsed().saturating_sub(save_time);
+        let _ = write_timings(err, load_time, command_time, save_time);
+    }
+    match result {
         Ok(_) => ExitCode::from(0),
-        Err(err) => {
-            println!("{}", err);
-            ExitCode::from(1)
-        }
+        Err(error) => exit_after_output(writeln!(out, "{}", error), ExitCode::from(1), err),
     }
 }
 
-/// Handles the provided command by delegating to the appropriate function.
+/// Options given before the command and applying to every command.
+#[derive(Debug, Default, PartialEq)]
+struct GlobalOptions {
+    /// The task file to use instead of the one from `TASK_FILE`.
+    task_file: Option<PathBuf>,
+    /// The layout to save the task file with, instead of the one it was loaded from.
+    layout: Option<FileLayout>,
+    /// Whether new tasks reuse the IDs of deleted tasks.
+    reuse_ids: bool,
+    /// Whether mutating commands are refused.
+    readonly: bool,
+    /// Whether missing parent directories of the task file are created, even when it is given.
+    ensure_dir: bool,
+    /// Whether the time spent loading, running the command and saving is printed.
+    profile_timing: bool,
+    /// The language of the labels and dates meant to be read, English if not given.
+    locale: Option<Locale>,
+    /// The number of tasks above which a bulk deletion asks for confirmation.
+    confirm_threshold: Option<usize>,
+    /// The file keeping the output of `list --json` between runs.
+    json_cache: Option<PathBuf>,
+}
+
+/// The settings a command runs with.
+#[derive(Debug)]
+struct Context {
+    /// The file tasks are loaded from and saved to.
+    task_file: PathBuf,
+    /// Whether missing parent directories of the task file are created when saving.
+    create_parent_dirs: bool,
+    /// Whether saving is refused, so that mutating commands fail without touching the file.
+    readonly: bool,
+    /// The time spent saving the task file so far.
+    save_time: Cell<Duration>,
+    /// The number of times the task file was saved so far.
+    saves: Cell<usize>,
+    /// The version of the task file the tasks were loaded from, or last saved to.
+    loaded_version: Cell<FileVersion>,
+    /// The language of the labels and dates meant to be read.
+    locale: Locale,
+    /// The number of tasks a bulk deletion removes without asking for confirmation.
+    confirm_threshold: usize,
+}
+
+/// Prints how long each phase of a run took, in milliseconds.
+///
+/// # Arguments
+///
+/// * `err` - The writer receiving the timings.
+/// * `load` - The time spent loading the task file.
+/// * `command` - The time spent running the command, saving excluded.
+/// * `save` - The time spent saving the task file.
+fn write_timings(
+    err: &mut impl Write,
+    load: Duration,
+    command: Duration,
+    save: Duration,
+) -> io::Result<()> {
+    writeln!(
+        err,
+        "load: {}ms, command: {}ms, save: {}ms",
+        load.as_millis(),
+        command.as_millis(),
+        save.as_millis()
+    )
+}
+
+/// Parses the global options given between the program name and the command.
 ///
 /// # Arguments
 ///
 /// * `args` - A slice of command-line arguments.
-/// * `repo` - A mutable reference to the `TaskRepository`.
 ///
 /// # Returns
 ///
-/// A `Result` indicating the success or failure of the operation.
-fn handle_command(args: &[String], repo: &mut TaskRepository) -> Result<(), String> {
-    let param1 = &args[1];
-    match param1.as_str() {
-        "list" => handle_list_command(args, repo),
-        "add" => handle_add_command(args, repo),
-        "delete" => handle_delete_command(args, repo),
-        "update" => handle_update_command(args, repo),
-        "mark-in-progress" => handle_mark_in_progress_command(args, repo),
-        _ => Err(format!("Unknown parameter {}", param1)),
+/// The parsed `GlobalOptions` and the arguments without them, so that the command is
+/// still at index 1, or an error message for malformed options.
+fn parse_global_options(args: &[String]) -> Result<(GlobalOptions, Vec<String>), TaskError> {
+    let mut options = GlobalOptions::default();
+    let mut remaining = args.iter().skip(1);
+    let mut command_args: Vec<String> = args.iter().take(1).cloned().collect();
+    while let Some(arg) = remaining.next() {
+        match arg.as_str() {
+            "--file" | "-f" => {
+                let value = remaining
+                    .next()
+                    .ok_or(format!("Missing path for {}", arg))?;
+                set_once(&mut options.task_file, PathBuf::from(value), arg)?;
+            }
+            "--layout" => {
+                let value = remaining
+                    .next()
+                    .ok_or(TaskError::MissingArgument("layout for --layout"))?;
+                set_once(&mut options.layout, parse_layout(value)?, arg)?;
+            }
+            "--reuse-ids" => options.reuse_ids = true,
+            "--readonly" => options.readonly = true,
+            "--ensure-dir" => options.ensure_dir = true,
+            "--profile-timing" => options.profile_timing = true,
+            "--locale" => {
+                let value = remaining
+                    .next()
+                    .ok_or(TaskError::MissingArgument("locale for --locale"))?;
+                set_once(&mut options.locale, value.parse()?, arg)?;
+            }
+            "--json-cache" => {
+                let value = remaining
+                    .next()
+                    .ok_or(TaskError::MissingArgument("path for --json-cache"))?;
+                set_once(&mut options.json_cache, PathBuf::from(value), arg)?;
+            }
+            "--confirm-threshold" => {
+                let value = remaining
+                    .next()
+                    .ok_or(TaskError::MissingArgument("number for --confirm-threshold"))?;
+                let threshold = value
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid confirmation threshold: {}", value))?;
+                set_once(&mut options.confirm_threshold, threshold, arg)?;
+            }
+            _ => {
+                command_args.push(arg.clone());
+                command_args.extend(remaining.cloned());
+                break;
+            }
+        }
     }
+    Ok((options, command_args))
 }
 
-/// Handles the "list" command to display tasks.
+/// Stores the value of an option that may be given only once.
 ///
 /// # Arguments
 ///
-/// * `args` - A slice of command-line arguments.
-/// * `repo` - A reference to the `TaskRepository`.
+/// * `slot` - Where the value of the option is stored, `None` until it is given.
+/// * `value` - The value given to the option.
+/// * `option` - The name of the option, for the error message.
 ///
 /// # Returns
 ///
-/// A `Result` indicating the success or failure of the operation.
-fn handle_list_command(args: &[String], repo: &TaskRepository) -> Result<(), String> {
-    if args.len() == 2 {
-        print_tasks(repo);
-    } else if args.len() == 3 {
-        match args[2].as_str() {
-            "todo" => print_tasks_by_status(repo, TaskStatus::Todo),
-            "done" => print_tasks_by_status(repo, TaskStatus::Done),
-            "in-progress" => print_tasks_by_status(repo, TaskStatus::InProgress),
-            _ => return Err("Unknown status to display".to_string()),
-        }
+/// An error message if the option was already given.
+fn set_once<T>(slot: &mut Option<T>, value: T, option: &str) -> Result<(), TaskError> {
+    if slot.is_some() {
+        return Err(TaskError::Invalid(format!(
+            "Option {} given more than once",
+            option
+        )));
     }
+    *slot = Some(value);
     Ok(())
 }
 
-/// Handles the "add" command to add a new task.
+/// Resolves the task file, giving precedence to the `--file` option over `TASK_FILE`,
+/// and falling back to `.tasklist.json` in the home directory.
+///
+/// # Arguments
+///
+/// * `option` - The path given with `--file`, if any.
+/// * `env_value` - The value of the `TASK_FILE` environment variable, if set.
+/// * `home` - The home directory, if known.
+///
+/// # Returns
+///
+/// The path of the task file, or `None` if none of them is set.
+fn resolve_task_file(
+    option: Option<PathBuf>,
+    env_value: Option<OsString>,
+    home: Option<OsString>,
+) -> Option<PathBuf> {
+    option
+        .or(env_value.map(PathBuf::from))
+        .or(home.map(|home| PathBuf::from(home).join(DEFAULT_TASK_FILE_NAME)))
+}
+
+/// Handles the provided command by delegating to the appropriate function.
 ///
 /// # Arguments
 ///
 /// * `args` - A slice of command-line arguments.
 /// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `ctx` - The `Context` the command runs in.
+/// * `out` - The writer receiving the output of the command.
 ///
 /// # Returns
 ///
 /// A `Result` indicating the success or failure of the operation.
-fn handle_add_command(args: &[String], repo: &mut TaskRepository) -> Result<(), String> {
-    if args.len() < 3 {
-        return Err("Missing description to add a new task".to_string());
+fn handle_command(
+    args: &[String],
+    repo: &mut TaskRepository,
+    ctx: &Context,
+    out: &mut impl Write,
+) -> Result<(), TaskError> {
+    let param1 = &args[1];
+    match param1.as_str() {
+        "list" => handle_list_command(args, repo, ctx.locale, out),
+        "add" => handle_add_command(args, repo, ctx),
+        "delete" => handle_delete_command(args, repo, ctx, &mut io::stdin().lock(), out),
+        "update" => handle_update_command(args, repo, ctx),
+        "edit" => handle_edit_command(args, repo, ctx, &mut io::stdin().lock(), out),
+        "mark-in-progress" => handle_mark_in_progress_command(args, repo, ctx),
+        "mark-todo" => handle_mark_todo_command(args, repo, ctx),
+        "recent" => handle_recent_command(args, repo, out),
+        "recent-done" => handle_recent_done_command(args, repo, out),
+        "search" => handle_search_command(args, repo, out),
+        "stats" => handle_stats_command(args, repo, out),
+        "peek" => handle_peek_command(repo, out),
+        "show" => handle_show_command(args, repo, ctx.locale, out),
+        "count" => handle_count_command(args, repo, out),
+        "export" => handle_export_command(args, repo, out),
+        "dump" => handle_dump_command(args, repo, out),
+        "restore" => handle_restore_command(args, repo, ctx, out),
+        "schema" => handle_schema_command(out),
+        "reopen" => handle_reopen_command(args, repo, ctx),
+        "set" => handle_set_command(args, repo, ctx),
+        "set-due" => handle_set_due_command(args, repo, ctx),
+        "bump" => handle_bump_command(args, repo, ctx),
+        "tag" => handle_tag_command(args, repo, ctx),
+        "untag" => handle_untag_command(args, repo, ctx),
+        "check-add" => handle_check_add_command(args, repo, ctx),
+        "check-toggle" => handle_check_toggle_command(args, repo, ctx),
+        "purge" => handle_purge_command(args, repo, ctx, &mut io::stdin().lock(), out),
+        "undo" => handle_undo_command(repo, ctx),
+        "renumber" => handle_renumber_command(repo, ctx, out),
+        "import" => handle_import_command(args, repo, ctx, out),
+        "prioritize" => handle_prioritize_command(repo, ctx, &mut io::stdin().lock(), out),
+        "help" => ignore_broken_pipe(write!(out, "{}", usage())),
+        _ => Err(TaskError::Invalid(format!(
+            "Unknown parameter {}\n\n{}",
+            param1,
+            usage()
+        ))),
+    }
+}
+
+/// The commands listed by `usage`, with their arguments and a one-line description.
+const COMMANDS: &[(&str, &str, &str)] = &[
+    (
+        "add",
+        "words... [priority] [--id id] [--tag name]... [--keep-hashtags]",
+        "Add a task",
+    ),
+    (
+        "add",
+        "description --at timestamp",
+        "Add a task created at the given ISO-8601 time",
+    ),
+    (
+        "add",
+        "--from-file path [priority]",
+        "Add a task described by the content of a file",
+    ),
+    (
+        "delete",
+        "ids... [--yes] [--dry-run]",
+        "Delete tasks after confirming each one, ids may be ranges like 1-5",
+    ),
+    (
+        "delete",
+        "--older-than duration [--yes] [--dry-run]",
+        "Delete tasks created before the given age",
+    ),
+    (
+        "list",
+        "[status...] [overdue] [--since-id id] [--tag name]",
+        "List tasks, all of them by default",
+    ),
+    (
+        "list",
+        "[--sort field] [--reverse] [--stale-after duration]",
+        "Choose the order and mark stale tasks",
+    ),
+    (
+        "list",
+        "[--limit n] [--offset n]",
+        "Print only a page of the sorted tasks",
+    ),
+    (
+        "list",
+        "[--status s,s] [--json [--utc]] [--compact-empty]",
+        "Filter by statuses, print as JSON or nothing if none matches",
+    ),
+    (
+        "list",
+        "--ids",
+        "Print only the ids of the tasks, one per line",
+    ),
+    (
+        "list",
+        "--group-by due",
+        "Group tasks by overdue, today, this week, later or no due date",
+    ),
+    (
+        "update",
+        "id description",
+        "Update the description of a task",
+    ),
+    (
+        "edit",
+        "id",
+        "Replace the description of a task with one read from the input",
+    ),
+    ("mark-in-progress", "id", "Move a task to in progress"),
+    ("mark-todo", "id", "Move a task back to todo"),
+    ("reopen", "id", "Move a done task back to todo"),
+    (
+        "set",
+        "id description|status|priority|due value",
+        "Set a single field of a task",
+    ),
+    ("set-due", "id date", "Set the due date of a task"),
+    (
+        "bump",
+        "id",
+        "Make a task due today and move it back to todo",
+    ),
+    ("tag", "id name", "Add a tag to a task"),
+    ("untag", "id name", "Remove a tag from a task"),
+    (
+        "check-add",
+        "id text",
+        "Add an item to the checklist of a task",
+    ),
+    (
+        "check-toggle",
+        "id index",
+        "Check or uncheck a checklist item, the first being 1",
+    ),
+    ("purge", "[--yes]", "Delete every done task"),
+    (
+        "renumber",
+        "",
+        "Give the tasks the ids 1 to N, keeping their order",
+    ),
+    (
+        "undo",
+        "",
+        "Bring back the tasks as they were before the last change",
+    ),
+    (
+        "import",
+        "path [--on-conflict skip|rename|replace]",
+        "Add a task for each line of a file",
+    ),
+    (
+        "prioritize",
+        "",
+        "Ask for the priority of each task not done",
+    ),
+    ("recent", "[count]", "List the most recently updated tasks"),
+    (
+        "recent-done",
+        "[count]",
+        "List the most recently completed tasks",
+    ),
+    (
+        "search",
+        "query",
+        "List tasks whose description contains the query",
+    ),
+    (
+        "stats",
+        "[--json]",
+        "Print the number of tasks per status, or the completion rate as JSON",
+    ),
+    ("show", "id", "Print every field of a task"),
+    (
+        "peek",
+        "",
+        "Print the task due the soonest, or else of the highest priority",
+    ),
+    (
+        "count",
+        "[--active|status|--by-priority]",
+        "Print the number of tasks, of tasks not done, with a status or per priority",
+    ),
+    (
+        "export",
+        "[--format markdown|ical]",
+        "Print the tasks as a Markdown checkbox list or those due as iCalendar to-dos",
+    ),
+    (
+        "dump",
+        "[--out path]",
+        "Write every task as JSON, to a file with --out",
+    ),
+    ("restore", "path", "Replace the tasks with those of a dump"),
+    ("schema", "", "Print the JSON Schema of the task file"),
+    ("help", "", "Print this help"),
+];
+
+/// Builds the help text listing every command with its arguments and description.
+///
+/// # Returns
+///
+/// The help text, ending with a newline.
+fn usage() -> String {
+    let mut usage = String::from(
+        "Usage: tasklist [--file path] [--layout wrapped|array] [--reuse-ids] [--readonly] [--ensure-dir] [--profile-timing] [--locale en|fr] [--confirm-threshold n] [--json-cache path] <command> <args>\n\nCommands:\n",
+    );
+    for (command, args, description) in COMMANDS {
+        usage.push_str(&format!("  {:<17} {:<54} {}\n", command, args, description));
+    }
+    usage
+}
+
+/// Options accepted by the "list" command.
+#[derive(Debug, Default, PartialEq)]
+struct ListOptions {
+    /// Only display tasks with one of these statuses, all tasks if empty.
+    statuses: Vec<TaskStatus>,
+    /// Only display tasks with an id greater than this one.
+    since_id: Option<i32>,
+    /// Mark tasks not updated for longer than this as stale.
+    stale_after: Option<TimeDelta>,
+    /// The order in which tasks are displayed.
+    order: ListOrder,
+    /// Only display tasks not done whose due date is past.
+    overdue: bool,
+    /// Only display tasks carrying this tag.
+    tag: Option<String>,
+    /// Print nothing instead of a message when no task matches.
+    compact_empty: bool,
+    /// Print the tasks as a JSON array instead of one sentence per task.
+    json: bool,
+    /// Print the times of the JSON tasks in UTC rather than with the local offset.
+    utc: bool,
+    /// Print only the ids of the tasks, one per line.
+    ids: bool,
+    /// Print the tasks under a header for each `DueBucket`.
+    group_by_due: bool,
+    /// Display at most this many tasks.
+    limit: Option<usize>,
+    /// Skip this many of the sorted tasks before displaying any.
+    offset: Option<usize>,
+}
+
+impl ListOptions {
+    /// Tells whether a task passes the overdue, id, status and tag filters.
+    ///
+    /// # Arguments
+    ///
+    /// * `task` - A reference to the `Task` to check.
+    /// * `now` - The time past which due tasks are overdue.
+    fn matches(&self, task: &Task, now: DateTime<Local>) -> bool {
+        (!self.overdue || task.is_overdue(now))
+            && self.since_id.is_none_or(|since_id| task.id > since_id)
+            && (self.statuses.is_empty() || self.statuses.contains(&task.status))
+            && self
+                .tag
+                .as_ref()
+                .is_none_or(|tag| task.tags.iter().any(|task_tag| task_tag == tag))
+    }
+
+    /// Builds the message printed when no task matches, naming the most specific filter.
+    fn empty_message(&self) -> String {
+        if self.overdue {
+            "No overdue task".to_string()
+        } else if let Some(since_id) = self.since_id {
+            format!("No task with id greater than {}", since_id)
+        } else if !self.statuses.is_empty() {
+            let statuses: Vec<String> = self.statuses.iter().map(ToString::to_string).collect();
+            format!("No task with status {}", statuses.join(" or "))
+        } else if let Some(tag) = &self.tag {
+            format!("No task with tag {}", tag)
+        } else {
+            "Your task list is empty.".to_string()
+        }
+    }
+}
+
+/// How soon a task is due, for grouping tasks when listed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DueBucket {
+    Overdue,
+    Today,
+    ThisWeek,
+    Later,
+    NoDueDate,
+}
+
+impl DueBucket {
+    /// Every bucket, in the order they are displayed.
+    const ALL: [DueBucket; 5] = [
+        DueBucket::Overdue,
+        DueBucket::Today,
+        DueBucket::ThisWeek,
+        DueBucket::Later,
+        DueBucket::NoDueDate,
+    ];
+
+    /// Finds the bucket of a due date.
+    ///
+    /// A week runs from Monday to Sunday, so on Sundays nothing is due this week but not today.
+    ///
+    /// # Arguments
+    ///
+    /// * `due_date` - The due date of a task, if any.
+    /// * `now` - The reference time the due date is compared to.
+    fn of(due_date: Option<DateTime<Local>>, now: DateTime<Local>) -> DueBucket {
+        let Some(due_date) = due_date else {
+            return DueBucket::NoDueDate;
+        };
+        let (due_day, today) = (due_date.date_naive(), now.date_naive());
+        if due_date < now {
+            DueBucket::Overdue
+        } else if due_day == today {
+            DueBucket::Today
+        } else if due_day.iso_week() == today.iso_week() {
+            DueBucket::ThisWeek
+        } else {
+            DueBucket::Later
+        }
+    }
+
+    /// Returns the header printed above the tasks of the bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `locale` - The language of the header.
+    fn header(self, locale: Locale) -> &'static str {
+        match (locale, self) {
+            (Locale::English, DueBucket::Overdue) => "Overdue",
+            (Locale::English, DueBucket::Today) => "Today",
+            (Locale::English, DueBucket::ThisWeek) => "This week",
+            (Locale::English, DueBucket::Later) => "Later",
+            (Locale::English, DueBucket::NoDueDate) => "No due date",
+            (Locale::French, DueBucket::Overdue) => "En retard",
+            (Locale::French, DueBucket::Today) => "Aujourd'hui",
+            (Locale::French, DueBucket::ThisWeek) => "Cette semaine",
+            (Locale::French, DueBucket::Later) => "Plus tard",
+            (Locale::French, DueBucket::NoDueDate) => "Sans échéance",
+        }
+    }
+}
+
+/// The field tasks are sorted by when listed.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum SortField {
+    #[default]
+    Id,
+    Created,
+    Updated,
+    Status,
+}
+
+/// What `import` does with a line whose description is already the one of a task.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum ImportConflict {
+    /// The line is not imported.
+    Skip,
+    /// The line is imported as a new task, with its own id.
+    #[default]
+    Rename,
+    /// The task is overwritten by a new `Todo` one keeping its id.
+    Replace,
+}
+
+/// A task serialized as in the task file, except for its times being in UTC.
+///
+/// The task file keeps the local offset of each time, which `list --json --utc` replaces with
+/// the `Z` of UTC so that the output reads the same in every timezone.
+#[derive(Serialize)]
+struct UtcTask<'a> {
+    id: i32,
+    description: &'a str,
+    status: &'a TaskStatus,
+    priority: Priority,
+    #[serde(serialize_with = "serialize_utc")]
+    created_at: DateTime<Local>,
+    #[serde(serialize_with = "serialize_utc")]
+    updated_at: DateTime<Local>,
+    #[serde(serialize_with = "serialize_optional_utc")]
+    due_date: Option<DateTime<Local>>,
+    tags: &'a [Tag],
+    #[serde(serialize_with = "serialize_optional_utc")]
+    completed_at: Option<DateTime<Local>>,
+    checklist: &'a [(String, bool)],
+}
+
+impl<'a> From<&'a Task> for UtcTask<'a> {
+    fn from(task: &'a Task) -> Self {
+        UtcTask {
+            id: task.id,
+            description: &task.description,
+            status: &task.status,
+            priority: task.priority,
+            created_at: task.created_at,
+            updated_at: task.updated_at,
+            due_date: task.due_date,
+            tags: &task.tags,
+            completed_at: task.completed_at,
+            checklist: &task.checklist,
+        }
+    }
+}
+
+/// Serializes a time as an ISO-8601 string in UTC, such as `2024-05-01T08:30:00Z`.
+///
+/// # Arguments
+///
+/// * `time` - The time to serialize.
+/// * `serializer` - The serializer to write it to.
+fn serialize_utc<S: Serializer>(time: &DateTime<Local>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&time.to_utc().to_rfc3339_opts(SecondsFormat::AutoSi, true))
+}
+
+/// Serializes an optional time as an ISO-8601 string in UTC, or as `null` if there is none.
+///
+/// # Arguments
+///
+/// * `time` - The time to serialize, if any.
+/// * `serializer` - The serializer to write it to.
+fn serialize_optional_utc<S: Serializer>(
+    time: &Option<DateTime<Local>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match time {
+        Some(time) => serialize_utc(time, serializer),
+        None => serializer.serialize_none(),
     }
-    add_task(repo, args[2].clone());
-    Ok(())
 }
 
-/// Handles the "delete" command to delete a task.
+/// The order in which the "list" command displays tasks.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct ListOrder {
+    /// The field tasks are sorted by.
+    field: SortField,
+    /// Whether to sort in descending order.
+    reverse: bool,
+}
+
+/// Parses the arguments of the "list" command.
 ///
 /// # Arguments
 ///
 /// * `args` - A slice of command-line arguments.
-/// * `repo` - A mutable reference to the `TaskRepository`.
 ///
 /// # Returns
 ///
-/// A `Result` indicating the success or failure of the operation.
-fn handle_delete_command(args: &[String], repo: &mut TaskRepository) -> Result<(), String> {
-    if args.len() < 3 {
-        return Err("Missing id of task to delete".to_string());
+/// The parsed `ListOptions`, or an error message for unknown or malformed arguments.
+fn parse_list_options(args: &[String]) -> Result<ListOptions, TaskError> {
+    let mut options = ListOptions::default();
+    let mut sort_field = None;
+    let mut remaining = args.iter().skip(2);
+    while let Some(arg) = remaining.next() {
+        match arg.as_str() {
+            "--since-id" => {
+                let value = remaining
+                    .next()
+                    .ok_or(TaskError::MissingArgument("id for --since-id"))?;
+                set_once(&mut options.since_id, parse_id(value)?, arg)?;
+            }
+            "--stale-after" => {
+                let value = remaining
+                    .next()
+                    .ok_or(TaskError::MissingArgument("duration for --stale-after"))?;
+                set_once(&mut options.stale_after, parse_duration(value)?, arg)?;
+            }
+            "--sort" => {
+                let value = remaining
+                    .next()
+                    .ok_or(TaskError::MissingArgument("field for --sort"))?;
+                set_once(&mut sort_field, parse_sort_field(value)?, arg)?;
+            }
+            "--reverse" => options.order.reverse = true,
+            "--compact-empty" => options.compact_empty = true,
+            "--json" => options.json = true,
+            "--utc" => options.utc = true,
+            "--ids" => options.ids = true,
+            "--group-by" => {
+                let value = remaining
+                    .next()
+                    .ok_or(TaskError::MissingArgument("field for --group-by"))?;
+                if value != "due" {
+                    return Err(TaskError::Invalid(format!("Unknown grouping: {}", value)));
+                }
+                options.group_by_due = true;
+            }
+            "overdue" => options.overdue = true,
+            "--tag" => {
+                let value = remaining
+                    .next()
+                    .ok_or(TaskError::MissingArgument("name for --tag"))?;
+                set_once(&mut options.tag, value.clone(), arg)?;
+            }
+            "--limit" => {
+                let value = remaining
+                    .next()
+                    .ok_or(TaskError::MissingArgument("number for --limit"))?;
+                let limit = value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|limit| *limit > 0)
+                    .ok_or(format!("Invalid limit: {}", value))?;
+                set_once(&mut options.limit, limit, arg)?;
+            }
+            "--offset" => {
+                let value = remaining
+                    .next()
+                    .ok_or(TaskError::MissingArgument("number for --offset"))?;
+                let offset = value
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid offset: {}", value))?;
+                set_once(&mut options.offset, offset, arg)?;
+            }
+            "--status" => {
+                let value = remaining
+                    .next()
+                    .ok_or(TaskError::MissingArgument("statuses for --status"))?;
+                for status in value.split(',') {
+                    let status: TaskStatus = status.trim().parse()?;
+                    if !options.statuses.contains(&status) {
+                        options.statuses.push(status);
+                    }
+                }
+            }
+            _ => {
+                let status = parse_status(arg).ok_or("Unknown status to display".to_string())?;
+                if !options.statuses.contains(&status) {
+                    options.statuses.push(status);
+                }
+            }
+        }
     }
-    delete_task(repo, args[2].clone().parse::<i32>().unwrap());
-    Ok(())
+    if options.json && options.ids {
+        return Err(TaskError::Invalid(
+            "Cannot give both --json and --ids".to_string(),
+        ));
+    }
+    if options.utc && !options.json {
+        return Err(TaskError::Invalid(
+            "Cannot give --utc without --json".to_string(),
+        ));
+    }
+    if options.group_by_due && (options.json || options.ids) {
+        return Err(TaskError::Invalid(
+            "Cannot give --group-by with --json or --ids".to_string(),
+        ));
+    }
+    options.order.field = sort_field.unwrap_or_default();
+    Ok(options)
 }
 
-/// Handles the "update" command to update a task's description.
+/// Handles the "list" command to display tasks.
 ///
 /// # Arguments
 ///
 /// * `args` - A slice of command-line arguments.
-/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `repo` - A reference to the `TaskRepository`.
+/// * `locale` - The language of the labels and dates meant to be read.
+/// * `out` - The writer receiving the output of the command.
 ///
 /// # Returns
 ///
 /// A `Result` indicating the success or failure of the operation.
-fn handle_update_command(args: &[String], repo: &mut TaskRepository) -> Result<(), String> {
-    if args.len() < 4 {
-        return Err("Missing update parameters".to_string());
-    }
-    update_task(
-        repo,
-        args[2].clone().parse::<i32>().unwrap(),
-        args[3].clone(),
-    );
-    Ok(())
+fn handle_list_command(
+    args: &[String],
+    repo: &TaskRepository,
+    locale: Locale,
+    out: &mut impl Write,
+) -> Result<(), TaskError> {
+    let options = parse_list_options(args)?;
+    ignore_broken_pipe(print_listed_tasks(repo, &options, locale, out))
 }
 
-/// Handles the "mark-in-progress" command to mark a task as in progress.
+/// Handles the "add" command to add a new task.
+///
+/// The creation time defaults to now and can be backdated with `--at <timestamp>`.
+/// The id defaults to the next free one and can be chosen with `--id <id>`.
+/// The description can be read from a file with `--from-file <path>`.
+/// A priority (`low`, `medium` or `high`) can follow the description, `medium` otherwise.
+/// Tags are added with `--tag <name>`, which can be repeated.
+/// Words of the description starting with `#` are added as tags too, and removed from the
+/// description unless `--keep-hashtags` is given.
+///
+/// The arguments that are not options are joined with spaces into the description, except
+/// the last one when it names a priority and is not the only one. After `--`, every argument
+/// belongs to the description, even one looking like an option or a priority.
 ///
 /// # Arguments
 ///
 /// * `args` - A slice of command-line arguments.
 /// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `ctx` - The `Context` the command runs in.
 ///
 /// # Returns
 ///
-fn handle_mark_in_progress_command(
+/// A `Result` indicating the success or failure of the operation.
+fn handle_add_command(
     args: &[String],
     repo: &mut TaskRepository,
-) -> Result<(), String> {
-    if args.len() < 3 {
-        return Err("Missing id of task to progress".to_string());
+    ctx: &Context,
+) -> Result<(), TaskError> {
+    let mut created_at = None;
+    let mut id = None;
+    let mut from_file = None;
+    let mut tags = Vec::new();
+    let mut keep_hashtags = false;
+    let mut positional = Vec::new();
+    let mut terminated = false;
+    let mut remaining = args.iter().skip(2);
+    while let Some(arg) = remaining.next() {
+        match arg.as_str() {
+            "--at" => {
+                let value = remaining
+                    .next()
+                    .ok_or(TaskError::MissingArgument("timestamp for --at"))?;
+                set_once(&mut created_at, parse_timestamp(value)?, arg)?;
+            }
+            "--id" => {
+                let value = remaining
+                    .next()
+                    .ok_or(TaskError::MissingArgument("id for --id"))?;
+                set_once(&mut id, parse_id(value)?, arg)?;
+            }
+            "--from-file" => {
+                let value = remaining
+                    .next()
+                    .ok_or(TaskError::MissingArgument("path for --from-file"))?;
+                set_once(&mut from_file, value, arg)?;
+            }
+            "--tag" => {
+                let value = remaining
+                    .next()
+                    .ok_or(TaskError::MissingArgument("name for --tag"))?;
+                tags.push(value.clone());
+            }
+            "--keep-hashtags" => keep_hashtags = true,
+            "--" => {
+                positional.extend(remaining.by_ref());
+                terminated = true;
+            }
+            _ => positional.push(arg),
+        }
     }
-    mark_in_progress(repo, args[2].clone().parse::<i32>().unwrap());
-    Ok(())
+
+    let mut priority = Priority::default();
+    if !terminated && (positional.len() > 1 || from_file.is_some()) {
+        if let Some(Ok(parsed)) = positional.last().map(|arg| parse_priority(arg)) {
+            priority = parsed;
+            positional.pop();
+        }
+    }
+    let description = match &from_file {
+        Some(_) if !positional.is_empty() => {
+            return Err(TaskError::Invalid(
+                "Cannot give both a description and --from-file".to_string(),
+            ));
+        }
+        Some(path) => read_description_file(path)?,
+        None if positional.is_empty() => {
+            return Err(TaskError::MissingArgument("description to add a new task"));
+        }
+        None => positional
+            .iter()
+            .map(|arg| arg.as_str())
+            .collect::<Vec<_>>()
+            .join(" "),
+    };
+    let (stripped, hashtags) = extract_hashtags(&description);
+    tags.extend(hashtags);
+    let description = if keep_hashtags { description } else { stripped };
+    let new_task = NewTask {
+        description: clean_description(&description)?,
+        created_at: created_at.unwrap_or_else(Local::now),
+        id,
+        priority,
+        tags,
+    };
+    add_task(repo, new_task, ctx)
 }
 
-/// Prints tasks filtered by their status.
+/// Splits the hashtags out of a task description.
+///
+/// A hashtag is a word starting with `#` followed by the name of the tag, which cannot be only
+/// digits so that a reference such as `#42` stays in the text.
 ///
 /// # Arguments
 ///
-/// * `repo` - A reference to the `TaskRepository`.
-/// * `status` - The `TaskStatus` to filter tasks by.
-fn print_tasks_by_status(repo: &TaskRepository, status: TaskStatus) {
-    let task_list: Vec<_> = repo.tasks().filter(|task| task.status == status).collect();
-    if task_list.is_empty() {
-        println!("No task with status {}", status);
-    } else {
-        task_list.into_iter().for_each(print_task);
-    }
+/// * `desc` - The description to extract the hashtags of.
+///
+/// # Returns
+///
+/// The description without its hashtags, and the names of the tags in the order they appear.
+/// Each hashtag is removed along with the spaces separating it from the previous word, or from
+/// the next one at the start of a line, and the rest of the text is kept as it is.
+fn extract_hashtags(desc: &str) -> (String, Vec<String>) {
+    let mut tags = Vec::new();
+    let lines: Vec<String> = desc
+        .split('\n')
+        .map(|line| strip_line_hashtags(line, &mut tags))
+        .collect();
+    (lines.join("\n"), tags)
 }
 
-/// Prints all tasks in the repository.
+/// Removes the hashtags of a single line of a description, as done by `extract_hashtags`.
 ///
 /// # Arguments
 ///
-/// * `repository` - A reference to the `TaskRepository`.
-fn print_tasks(repository: &TaskRepository) {
-    if repository.task_count() > 0 {
-        repository.tasks().for_each(print_task);
-    } else {
-        println!("Your task list is empty.");
+/// * `line` - The line, without its newline.
+/// * `tags` - Where the names of the removed tags are appended.
+///
+/// # Returns
+///
+/// The line without its hashtags.
+fn strip_line_hashtags(line: &str, tags: &mut Vec<String>) -> String {
+    let mut kept = String::with_capacity(line.len());
+    let mut rest = line;
+    while !rest.is_empty() {
+        let (space, word_and_rest) = rest.split_at(rest.len() - rest.trim_start().len());
+        let word_end = word_and_rest
+            .find(char::is_whitespace)
+            .unwrap_or(word_and_rest.len());
+        let (word, next) = word_and_rest.split_at(word_end);
+        let tag = word
+            .strip_prefix('#')
+            .filter(|tag| !tag.is_empty() && !tag.bytes().all(|byte| byte.is_ascii_digit()));
+        match tag {
+            Some(tag) if kept.trim().is_empty() => {
+                // Nothing precedes it on the line: the indentation stays, the next word moves up.
+                tags.push(tag.to_string());
+                kept.push_str(space);
+                rest = next.trim_start();
+            }
+            Some(tag) => {
+                tags.push(tag.to_string());
+                rest = next;
+            }
+            None => {
+                kept.push_str(space);
+                kept.push_str(word);
+                rest = next;
+            }
+        }
     }
+    kept
 }
 
-/// Prints a single task.
+/// Trims a task description given on the command line, refusing one left empty.
 ///
 /// # Arguments
 ///
-/// * `task` - A reference to the `Task` to be printed.
-fn print_task(task: &Task) {
-    println!(
-        "Task {}: \"{}\" {}. Created at: {}. Last update: {}",
-        task.id, task.description, task.status, task.created_at, task.created_at
-    );
+/// * `description` - The description as given.
+///
+/// # Returns
+///
+/// The description without surrounding whitespace, or an error if nothing else remains.
+fn clean_description(description: &str) -> Result<String, TaskError> {
+    let description = description.trim();
+    if description.is_empty() {
+        return Err(TaskError::Invalid(
+            "Description cannot be empty".to_string(),
+        ));
+    }
+    Ok(description.to_string())
 }
 
-/// Adds a new task to the repository.
+/// Reads a task description from a file, without its trailing newline.
 ///
 /// # Arguments
 ///
-/// * `repo` - A mutable reference to the `TaskRepository`.
-/// * `desc` - A string describing the new task.
-fn add_task(repo: &mut TaskRepository, desc: String) {
-    repo.new_task(desc);
-    save_repository(repo);
+/// * `path` - The path of the file holding the description.
+///
+/// # Returns
+///
+/// The content of the file, or an error message if it cannot be read or is empty.
+fn read_description_file(path: &str) -> Result<String, TaskError> {
+    let content = fs::read_to_string(path).map_err(|err| {
+        TaskError::io(
+            format_args!("Failed to read description file {}", path),
+            err,
+        )
+    })?;
+    let description = content.trim_end_matches(['\n', '\r']);
+    if description.is_empty() {
+        return Err(TaskError::Invalid(format!(
+            "Description file {} is empty",
+            path
+        )));
+    }
+    Ok(description.to_string())
 }
 
-/// Deletes a task from the repository.
+/// Handles the "delete" command to delete one or more tasks.
+///
+/// Ids can be given individually or as inclusive ranges, e.g. `delete 1-5 8`.
+/// Each task is only deleted once confirmed on the input, unless `--yes` (or `-y`) is given.
+/// Alternatively, `delete --older-than <duration>` deletes every task created before the
+/// given age, whatever its status, asking once for confirmation when there are more of them
+/// than the confirmation threshold and `--yes` is not given.
+/// With `--dry-run`, the matched tasks are only printed, after a "Matched N task(s)" line.
 ///
 /// # Arguments
 ///
+/// * `args` - A slice of command-line arguments.
 /// * `repo` - A mutable reference to the `TaskRepository`.
-/// * `task_id` - The ID of the task to be deleted.
+/// * `ctx` - The `Context` the command runs in.
+/// * `input` - The reader providing the confirmations.
+/// * `out` - The writer receiving the output of the command.
 ///
 /// # Returns
 ///
-/// An `Option` containing the deleted `Task` if it existed.
-fn delete_task(repo: &mut TaskRepository, task_id: i32) -> Option<Task> {
-    let ret = repo.delete(task_id);
-    save_repository(repo);
-    ret
+/// A `Result` indicating the success or failure of the operation.
+fn handle_delete_command(
+    args: &[String],
+    repo: &mut TaskRepository,
+    ctx: &Context,
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+) -> Result<(), TaskError> {
+    let mut id_args = Vec::new();
+    let mut older_than = None;
+    let mut confirmed = false;
+    let mut dry_run = false;
+    let mut remaining = args.iter().skip(2);
+    while let Some(arg) = remaining.next() {
+        match arg.as_str() {
+            "--older-than" => {
+                let value = remaining
+                    .next()
+                    .ok_or(TaskError::MissingArgument("duration for --older-than"))?;
+                set_once(&mut older_than, parse_duration(value)?, arg)?;
+            }
+            "--yes" | "-y" => confirmed = true,
+            "--dry-run" => dry_run = true,
+            _ => id_args.push(arg.clone()),
+        }
+    }
+
+    if let Some(older_than) = older_than {
+        if !id_args.is_empty() {
+            return Err(TaskError::Invalid(
+                "Cannot delete by id and by age at the same time".to_string(),
+            ));
+        }
+        let now = Local::now();
+        let old_ids = tasks_older_than(repo, older_than, now);
+        if dry_run {
+            return ignore_broken_pipe(print_matched_tasks(repo, &old_ids, out));
+        }
+        if !confirm_bulk(
+            "Delete {} task(s)?",
+            old_ids.len(),
+            confirmed,
+            ctx,
+            input,
+            out,
+        )? {
+            return ignore_broken_pipe(writeln!(out, "Nothing deleted"));
+        }
+        let removed = delete_tasks_older_than(repo, older_than, now, ctx)?;
+        return ignore_broken_pipe(writeln!(out, "Removed {} task(s)", removed));
+    }
+
+    if id_args.is_empty() {
+        return Err(TaskError::MissingArgument("id of task to delete"));
+    }
+    let IdList { mut ids, missing } = parse_id_list(repo, &id_args)?;
+    let missing = missing.join(", ");
+    if ids.is_empty() {
+        return Err(TaskError::Invalid(format!("No task with id {}", missing)));
+    }
+    if dry_run {
+        return ignore_broken_pipe(print_matched_tasks(repo, &ids, out));
+    }
+    if !confirmed {
+        ids = confirm_deletions(repo, ids, input, out)?;
+        if ids.is_empty() {
+            return ignore_broken_pipe(writeln!(out, "Nothing deleted"));
+        }
+    }
+    delete_tasks(repo, &ids, ctx)?;
+    if !missing.is_empty() {
+        ignore_broken_pipe(writeln!(out, "No task with id {}", missing))?;
+    }
+    Ok(())
 }
 
-/// Updates the description of a task.
+/// Handles the "update" command to update a task's description.
 ///
 /// # Arguments
 ///
+/// * `args` - A slice of command-line arguments.
 /// * `repo` - A mutable reference to the `TaskRepository`.
-/// * `id` - The ID of the task to be updated.
-/// * `new_desc` - The new description for the task.
-fn update_task(repo: &mut TaskRepository, id: i32, new_desc: String) {
-    let task = repo.task(id);
-    task.description = new_desc;
-    save_repository(repo);
+/// * `ctx` - The `Context` the command runs in.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_update_command(
+    args: &[String],
+    repo: &mut TaskRepository,
+    ctx: &Context,
+) -> Result<(), TaskError> {
+    if args.len() < 4 {
+        return Err(TaskError::MissingArgument("update parameters"));
+    }
+    let description = clean_description(&args[3])?;
+    update_task(repo, parse_id(&args[2])?, description, ctx)
 }
 
-/// Saves the current state of the task repository to the file specified by the `TASK_FILE` environment variable.
+/// Handles the "edit" command to replace a task's description with a line read from the input.
+///
+/// The current description is printed first. An empty line, or the end of the input, keeps it
+/// and nothing is saved.
 ///
 /// # Arguments
 ///
+/// * `args` - A slice of command-line arguments.
 /// * `repo` - A mutable reference to the `TaskRepository`.
-fn save_repository(repo: &mut TaskRepository) {
-    println!("Saving tasks to {}", task_file_name());
-    task_repository::save_repository(repo, &task_file_name());
+/// * `ctx` - The `Context` the command runs in.
+/// * `input` - The reader providing the new description.
+/// * `out` - The writer receiving the output of the command.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_edit_command(
+    args: &[String],
+    repo: &mut TaskRepository,
+    ctx: &Context,
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+) -> Result<(), TaskError> {
+    if args.len() < 3 {
+        return Err(TaskError::MissingArgument("id of task to edit"));
+    }
+    let id = parse_id(&args[2])?;
+    let task = repo.task_ref(id).ok_or(TaskError::NotFound(id))?;
+    ignore_broken_pipe(writeln!(out, "Current description: {}", task.description))?;
+    ignore_broken_pipe(write!(out, "New description (empty to keep): "))?;
+    ignore_broken_pipe(out.flush())?;
+    let mut answer = String::new();
+    input
+        .read_line(&mut answer)
+        .map_err(|err| TaskError::io("Failed to read description", err))?;
+    let description = answer.trim_end_matches(['\n', '\r']);
+    if description.is_empty() {
+        return ignore_broken_pipe(writeln!(out, "Description unchanged"));
+    }
+    update_task(repo, id, clean_description(description)?, ctx)
 }
 
-/// Computes the name of the file to store tasks in.
-/// The name is read from the `TASK_FILE` environment variable, or defaults to "tasks_file.txt".
-fn task_file_name() -> String {
-    env::var("TASK_FILE").unwrap_or("tasks_file.txt".to_string())
+/// Handles the "mark-in-progress" command to mark a task as in progress.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `ctx` - The `Context` the command runs in.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_mark_in_progress_command(
+    args: &[String],
+    repo: &mut TaskRepository,
+    ctx: &Context,
+) -> Result<(), TaskError> {
+    if args.len() < 3 {
+        return Err(TaskError::MissingArgument("id of task to progress"));
+    }
+    mark_in_progress(repo, parse_id(&args[2])?, ctx)
 }
 
-/// Marks a task as in progress.
+/// Handles the "mark-todo" command to move a task back to todo.
 ///
 /// # Arguments
 ///
+/// * `args` - A slice of command-line arguments.
 /// * `repo` - A mutable reference to the `TaskRepository`.
-/// * `id` - The ID of the task to be marked as in progress.
-fn mark_in_progress(repo: &mut TaskRepository, id: i32) {
-    repo.task(id).status = TaskStatus::InProgress;
-    save_repository(repo);
+/// * `ctx` - The `Context` the command runs in.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_mark_todo_command(
+    args: &[String],
+    repo: &mut TaskRepository,
+    ctx: &Context,
+) -> Result<(), TaskError> {
+    if args.len() < 3 {
+        return Err(TaskError::MissingArgument("id of task to mark as todo"));
+    }
+    mark_todo(repo, parse_id(&args[2])?, ctx)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::path::Path;
-    use tempfile::{Builder, TempDir};
-
-    fn setup() {
-        let tmp_dir = Builder::new()
-            .prefix("task_list_test")
-            .rand_bytes(10)
-            .keep(true)
-            .tempdir()
-            .unwrap();
-        let _ = env::set_current_dir(&tmp_dir);
-    }
-    #[test]
-    fn task_added() {
-        setup();
+/// Handles the "reopen" command to move a done task back to todo.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `ctx` - The `Context` the command runs in.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_reopen_command(
+    args: &[String],
+    repo: &mut TaskRepository,
+    ctx: &Context,
+) -> Result<(), TaskError> {
+    if args.len() < 3 {
+        return Err(TaskError::MissingArgument("id of task to reopen"));
+    }
+    repo.reopen(parse_id(&args[2])?)?;
+    save_repository(repo, ctx)
+}
+
+/// Handles the "set-due" command to set the due date of a task.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `ctx` - The `Context` the command runs in.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_set_due_command(
+    args: &[String],
+    repo: &mut TaskRepository,
+    ctx: &Context,
+) -> Result<(), TaskError> {
+    if args.len() < 4 {
+        return Err(TaskError::MissingArgument("set-due parameters"));
+    }
+    let due_date = parse_due_date(&args[3], ctx.locale)?;
+    repo.set_due_date(parse_id(&args[2])?, Some(due_date))?;
+    save_repository(repo, ctx)
+}
+
+/// Handles the "bump" command to make a task due at the end of today, back in todo.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `ctx` - The `Context` the command runs in.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_bump_command(
+    args: &[String],
+    repo: &mut TaskRepository,
+    ctx: &Context,
+) -> Result<(), TaskError> {
+    if args.len() < 3 {
+        return Err(TaskError::MissingArgument("id of task to bump"));
+    }
+    let today = Local::now().date_naive();
+    let due_date = end_of_day(today).ok_or(format!("Invalid due date: {}", today))?;
+    repo.bump(parse_id(&args[2])?, due_date)?;
+    save_repository(repo, ctx)
+}
+
+/// Handles the "tag" command to add a tag to a task.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `ctx` - The `Context` the command runs in.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_tag_command(
+    args: &[String],
+    repo: &mut TaskRepository,
+    ctx: &Context,
+) -> Result<(), TaskError> {
+    if args.len() < 4 {
+        return Err(TaskError::MissingArgument("tag parameters"));
+    }
+    repo.add_tag(parse_id(&args[2])?, args[3].clone())?;
+    save_repository(repo, ctx)
+}
+
+/// Handles the "check-add" command to add an item to the checklist of a task.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `ctx` - The `Context` the command runs in.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_check_add_command(
+    args: &[String],
+    repo: &mut TaskRepository,
+    ctx: &Context,
+) -> Result<(), TaskError> {
+    if args.len() < 4 {
+        return Err(TaskError::MissingArgument("check-add parameters"));
+    }
+    repo.add_checklist_item(parse_id(&args[2])?, args[3].clone())?;
+    save_repository(repo, ctx)
+}
+
+/// Handles the "check-toggle" command to check or uncheck an item of the checklist of a task.
+///
+/// Items are numbered from 1, in the order they are displayed.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `ctx` - The `Context` the command runs in.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_check_toggle_command(
+    args: &[String],
+    repo: &mut TaskRepository,
+    ctx: &Context,
+) -> Result<(), TaskError> {
+    if args.len() < 4 {
+        return Err(TaskError::MissingArgument("check-toggle parameters"));
+    }
+    let index = args[3]
+        .parse::<usize>()
+        .ok()
+        .filter(|index| *index > 0)
+        .ok_or(format!("Invalid checklist item: {}", args[3]))?;
+    repo.toggle_checklist_item(parse_id(&args[2])?, index - 1)?;
+    save_repository(repo, ctx)
+}
+
+/// Handles the "untag" command to remove a tag from a task.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `ctx` - The `Context` the command runs in.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_untag_command(
+    args: &[String],
+    repo: &mut TaskRepository,
+    ctx: &Context,
+) -> Result<(), TaskError> {
+    if args.len() < 4 {
+        return Err(TaskError::MissingArgument("untag parameters"));
+    }
+    repo.remove_tag(parse_id(&args[2])?, &args[3])?;
+    save_repository(repo, ctx)
+}
+
+/// Handles the "import" command to add a task for each line of a file.
+///
+/// With `--on-conflict skip|rename|replace`, a line whose description is already the one of
+/// a task is ignored, imported as a new task (the default) or overwrites that task.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `ctx` - The `Context` the command runs in.
+/// * `out` - The writer receiving the output of the command.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_import_command(
+    args: &[String],
+    repo: &mut TaskRepository,
+    ctx: &Context,
+    out: &mut impl Write,
+) -> Result<(), TaskError> {
+    let mut path = None;
+    let mut on_conflict = None;
+    let mut remaining = args.iter().skip(2);
+    while let Some(arg) = remaining.next() {
+        match arg.as_str() {
+            "--on-conflict" => {
+                let value = remaining
+                    .next()
+                    .ok_or(TaskError::MissingArgument("policy for --on-conflict"))?;
+                set_once(&mut on_conflict, parse_import_conflict(value)?, arg)?;
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(TaskError::Invalid(format!("Unexpected argument: {}", arg))),
+        }
+    }
+    let path = path.ok_or(TaskError::MissingArgument("path of the file to import"))?;
+    let content = fs::read_to_string(path)
+        .map_err(|err| TaskError::io(format_args!("Failed to read import file {}", path), err))?;
+    let imported = import_tasks(repo, &content, on_conflict.unwrap_or_default())?;
+    save_repository(repo, ctx)?;
+    ignore_broken_pipe(writeln!(out, "Imported {} task(s)", imported))
+}
+
+/// Adds a `Todo` task for each line of the content.
+///
+/// Blank lines and lines starting with `#` are skipped, and descriptions are trimmed.
+///
+/// # Arguments
+///
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `content` - The text to import, one description per line.
+/// * `on_conflict` - What to do with a line whose description is already the one of a task,
+///   including one imported from an earlier line.
+///
+/// # Returns
+///
+/// The number of imported lines, skipped ones excluded, or an error message if a task cannot
+/// be replaced.
+fn import_tasks(
+    repo: &mut TaskRepository,
+    content: &str,
+    on_conflict: ImportConflict,
+) -> Result<usize, TaskError> {
+    let mut imported = 0;
+    let descriptions = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+    for description in descriptions {
+        let existing = repo
+            .tasks()
+            .find(|task| task.description == description)
+            .map(|task| task.id);
+        match (existing, on_conflict) {
+            (Some(_), ImportConflict::Skip) => continue,
+            (Some(id), ImportConflict::Replace) => {
+                repo.delete(id);
+                repo.new_task_with_id(id, description.to_string())?;
+            }
+            _ => repo.new_task(description.to_string()),
+        }
+        imported += 1;
+    }
+    Ok(imported)
+}
+
+/// Handles the "purge" command to delete every done task.
+///
+/// Purging more tasks than the confirmation threshold is only done once confirmed on the
+/// input, unless `--yes` (or `-y`) is given.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `ctx` - The `Context` the command runs in.
+/// * `input` - The reader providing the confirmation.
+/// * `out` - The writer receiving the output of the command.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_purge_command(
+    args: &[String],
+    repo: &mut TaskRepository,
+    ctx: &Context,
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+) -> Result<(), TaskError> {
+    let mut confirmed = false;
+    for arg in args.iter().skip(2) {
+        match arg.as_str() {
+            "--yes" | "-y" => confirmed = true,
+            _ => return Err(TaskError::Invalid(format!("Unexpected argument: {}", arg))),
+        }
+    }
+    let done = repo.count_by_status(TaskStatus::Done);
+    if !confirm_bulk("Delete {} done task(s)?", done, confirmed, ctx, input, out)? {
+        return ignore_broken_pipe(writeln!(out, "Nothing deleted"));
+    }
+    let removed = repo.purge_done();
+    save_repository(repo, ctx)?;
+    ignore_broken_pipe(writeln!(out, "Removed {} completed tasks", removed))
+}
+
+/// Handles the "renumber" command to make the task ids contiguous from 1.
+///
+/// # Arguments
+///
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `ctx` - The `Context` the command runs in.
+/// * `out` - The writer receiving the output of the command.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_renumber_command(
+    repo: &mut TaskRepository,
+    ctx: &Context,
+    out: &mut impl Write,
+) -> Result<(), TaskError> {
+    let changed = repo.renumber();
+    save_repository(repo, ctx)?;
+    ignore_broken_pipe(writeln!(out, "Renumbered {} tasks", changed))
+}
+
+/// Handles the "prioritize" command, asking for the priority of each task not done yet.
+///
+/// Tasks are visited in id order. An empty answer keeps the priority of the task, an unknown
+/// one asks again, and the end of the input stops the session. The repository is saved once
+/// at the end.
+///
+/// # Arguments
+///
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `ctx` - The `Context` the command runs in.
+/// * `input` - The reader providing the answers.
+/// * `out` - The writer receiving the prompts.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_prioritize_command(
+    repo: &mut TaskRepository,
+    ctx: &Context,
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+) -> Result<(), TaskError> {
+    let ids: Vec<i32> = repo
+        .tasks()
+        .filter(|task| task.status != TaskStatus::Done)
+        .map(|task| task.id)
+        .collect();
+    'tasks: for id in ids {
+        if let Some(task) = repo.task(id) {
+            ignore_broken_pipe(print_task(task, out))?;
+        }
+        loop {
+            ignore_broken_pipe(write!(out, "Priority (low, medium, high, empty to skip): "))?;
+            ignore_broken_pipe(out.flush())?;
+            let mut answer = String::new();
+            let read = input
+                .read_line(&mut answer)
+                .map_err(|err| TaskError::io("Failed to read answer", err))?;
+            if read == 0 {
+                break 'tasks;
+            }
+            match answer.trim() {
+                "" => break,
+                answer => match parse_priority(answer) {
+                    Ok(priority) => {
+                        repo.set_priority(id, priority)?;
+                        break;
+                    }
+                    Err(err) => ignore_broken_pipe(writeln!(out, "{}", err))?,
+                },
+            }
+        }
+    }
+    save_repository(repo, ctx)
+}
+
+/// Handles the "set" command to set a single field of a task.
+///
+/// The supported fields are `description`, `status`, `priority` and `due`, a due date of
+/// `none` clearing it.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `ctx` - The `Context` the command runs in.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_set_command(
+    args: &[String],
+    repo: &mut TaskRepository,
+    ctx: &Context,
+) -> Result<(), TaskError> {
+    if args.len() < 5 {
+        return Err(TaskError::MissingArgument("set parameters"));
+    }
+    let id = parse_id(&args[2])?;
+    let value = &args[4];
+    match args[3].as_str() {
+        "description" => repo.set_description(id, clean_description(value)?)?,
+        "status" => {
+            let status = parse_status(value).ok_or(format!("Invalid status: {}", value))?;
+            repo.set_status(id, status)?
+        }
+        "priority" => repo.set_priority(id, parse_priority(value)?)?,
+        "due" => {
+            let due_date = match value.as_str() {
+                "none" => None,
+                _ => Some(parse_due_date(value, ctx.locale)?),
+            };
+            repo.set_due_date(id, due_date)?
+        }
+        field => return Err(TaskError::Invalid(format!("Unknown field: {}", field))),
+    }
+    save_repository(repo, ctx)
+}
+
+/// Handles the "recent" command to display the most recently updated tasks.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+/// * `repo` - A reference to the `TaskRepository`.
+/// * `out` - The writer receiving the output of the command.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_recent_command(
+    args: &[String],
+    repo: &TaskRepository,
+    out: &mut impl Write,
+) -> Result<(), TaskError> {
+    let task_list = repo.most_recent(recent_count(args)?);
+    ignore_broken_pipe(if task_list.is_empty() {
+        writeln!(out, "Your task list is empty.")
+    } else {
+        task_list
+            .into_iter()
+            .try_for_each(|task| print_task(task, out))
+    })
+}
+
+/// Handles the "recent-done" command to display the most recently completed tasks.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+/// * `repo` - A reference to the `TaskRepository`.
+/// * `out` - The writer receiving the output of the command.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_recent_done_command(
+    args: &[String],
+    repo: &TaskRepository,
+    out: &mut impl Write,
+) -> Result<(), TaskError> {
+    let task_list = repo.recently_completed(recent_count(args)?);
+    ignore_broken_pipe(if task_list.is_empty() {
+        writeln!(out, "No task has been completed yet.")
+    } else {
+        task_list
+            .into_iter()
+            .try_for_each(|task| print_task(task, out))
+    })
+}
+
+/// Parses the optional number of tasks given to the "recent" and "recent-done" commands.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+///
+/// # Returns
+///
+/// The number of tasks to display, `DEFAULT_RECENT_COUNT` if none is given.
+fn recent_count(args: &[String]) -> Result<usize, TaskError> {
+    match args.get(2) {
+        Some(arg) => Ok(arg
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid number of tasks: {}", arg))?),
+        None => Ok(DEFAULT_RECENT_COUNT),
+    }
+}
+
+/// Handles the "count" command to print only a number of tasks.
+///
+/// Without argument, all tasks are counted. With `--active`, only tasks not done yet are.
+/// With a status, only tasks having that status are. With `--by-priority`, the tasks of each
+/// priority are counted, one line per priority.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+/// * `repo` - A reference to the `TaskRepository`.
+/// * `out` - The writer receiving the output of the command.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_count_command(
+    args: &[String],
+    repo: &TaskRepository,
+    out: &mut impl Write,
+) -> Result<(), TaskError> {
+    if args.get(2).is_some_and(|arg| arg == "--by-priority") {
+        if let Some(arg) = args.get(3) {
+            return Err(TaskError::Invalid(format!("Unexpected argument: {}", arg)));
+        }
+        return ignore_broken_pipe(print_priority_counts(repo, out));
+    }
+    let count = match args.get(2).map(String::as_str) {
+        None => repo.task_count(),
+        Some("--active") => repo.active_count(),
+        Some(arg) => match parse_status(arg) {
+            Some(status) => repo.count_by_status(status),
+            None => return Err(TaskError::Invalid(format!("Unexpected argument: {}", arg))),
+        },
+    };
+    if let Some(arg) = args.get(3) {
+        return Err(TaskError::Invalid(format!("Unexpected argument: {}", arg)));
+    }
+    ignore_broken_pipe(writeln!(out, "{}", count))
+}
+
+/// Handles the "stats" command to display the number of tasks per status.
+///
+/// With `--json`, the total and done numbers of tasks and the completion rate are printed as
+/// a single JSON object instead.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+/// * `repo` - A reference to the `TaskRepository`.
+/// * `out` - The writer receiving the output of the command.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_stats_command(
+    args: &[String],
+    repo: &TaskRepository,
+    out: &mut impl Write,
+) -> Result<(), TaskError> {
+    let mut json = false;
+    for arg in args.iter().skip(2) {
+        match arg.as_str() {
+            "--json" => json = true,
+            _ => return Err(TaskError::Invalid(format!("Unexpected argument: {}", arg))),
+        }
+    }
+    if !json {
+        return ignore_broken_pipe(print_stats(repo, out));
+    }
+    let stats = serde_json::to_string(&CompletionStats::of(repo))?;
+    ignore_broken_pipe(writeln!(out, "{}", stats))
+}
+
+/// The numbers printed by `stats --json`.
+#[derive(Debug, PartialEq, Serialize)]
+struct CompletionStats {
+    /// The number of tasks.
+    total: usize,
+    /// The number of done tasks.
+    done: usize,
+    /// The share of the tasks that are done, from 0 to 1, and 0 when there is no task.
+    completion_rate: f64,
+}
+
+impl CompletionStats {
+    /// Computes the completion numbers of the tasks of a repository.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - A reference to the `TaskRepository`.
+    fn of(repo: &TaskRepository) -> Self {
+        let total = repo.task_count();
+        let done = repo.count_by_status(TaskStatus::Done);
+        let completion_rate = if total == 0 {
+            0.0
+        } else {
+            done as f64 / total as f64
+        };
+        CompletionStats {
+            total,
+            done,
+            completion_rate,
+        }
+    }
+}
+
+/// Handles the "peek" command to display the task to work on next, without saving anything.
+///
+/// # Arguments
+///
+/// * `repo` - A reference to the `TaskRepository`.
+/// * `out` - The writer receiving the output of the command.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_peek_command(repo: &TaskRepository, out: &mut impl Write) -> Result<(), TaskError> {
+    ignore_broken_pipe(match repo.next_task() {
+        Some(task) => print_task(task, out),
+        None => writeln!(out, "Nothing pending"),
+    })
+}
+
+/// Handles the "show" command to display every field of a task, one per line.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+/// * `repo` - A reference to the `TaskRepository`.
+/// * `locale` - The language of the labels and dates meant to be read.
+/// * `out` - The writer receiving the output of the command.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_show_command(
+    args: &[String],
+    repo: &TaskRepository,
+    locale: Locale,
+    out: &mut impl Write,
+) -> Result<(), TaskError> {
+    if args.len() < 3 {
+        return Err(TaskError::MissingArgument("id of task to show"));
+    }
+    let id = parse_id(&args[2])?;
+    let task = repo.task_ref(id).ok_or(TaskError::NotFound(id))?;
+    ignore_broken_pipe(print_task_detail(task, locale, Local::now(), out))
+}
+
+/// Prints every field of a task on its own labeled line.
+///
+/// # Arguments
+///
+/// * `task` - A reference to the `Task` to be printed.
+/// * `locale` - The language the age of the last update is given in.
+/// * `now` - The reference time the age of the last update is computed from.
+/// * `out` - The writer receiving the output.
+fn print_task_detail(
+    task: &Task,
+    locale: Locale,
+    now: DateTime<Local>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let or_none = |value: Option<DateTime<Local>>| {
+        value.map_or("none".to_string(), |value| value.to_string())
+    };
+    writeln!(out, "Id: {}", task.id)?;
+    writeln!(out, "Description: {}", task.description)?;
+    writeln!(out, "Status: {}", task.status)?;
+    writeln!(out, "Priority: {}", task.priority)?;
+    writeln!(out, "Created at: {}", task.created_at)?;
+    writeln!(
+        out,
+        "Last update: {} ({})",
+        task.updated_at,
+        locale.humanize(now - task.updated_at)
+    )?;
+    writeln!(out, "Due: {}", or_none(task.due_date))?;
+    writeln!(out, "Completed at: {}", or_none(task.completed_at))?;
+    if task.tags.is_empty() {
+        writeln!(out, "Tags: none")?;
+    } else {
+        writeln!(out, "Tags: {}", task.tags.join(", "))?;
+    }
+    if task.checklist.is_empty() {
+        return writeln!(out, "Checklist: none");
+    }
+    let checked = task
+        .checklist
+        .iter()
+        .filter(|(_, checked)| *checked)
+        .count();
+    writeln!(out, "Checklist: {}/{} done", checked, task.checklist.len())?;
+    print_checklist(task, out)
+}
+
+/// Prints the number of tasks of each priority, from the lowest to the highest.
+///
+/// # Arguments
+///
+/// * `repo` - A reference to the `TaskRepository`.
+/// * `out` - The writer receiving the output.
+fn print_priority_counts(repo: &TaskRepository, out: &mut impl Write) -> io::Result<()> {
+    let counts = repo.priority_counts();
+    for priority in [Priority::Low, Priority::Medium, Priority::High] {
+        let count = counts.get(&priority).copied().unwrap_or(0);
+        writeln!(out, "{}: {}", priority, count)?;
+    }
+    Ok(())
+}
+
+/// Prints the total number of tasks followed by the number of tasks per status.
+///
+/// # Arguments
+///
+/// * `repo` - A reference to the `TaskRepository`.
+/// * `out` - The writer receiving the output.
+fn print_stats(repo: &TaskRepository, out: &mut impl Write) -> io::Result<()> {
+    let counts = repo.status_counts();
+    writeln!(out, "Total: {}", repo.task_count())?;
+    for status in [TaskStatus::Todo, TaskStatus::InProgress, TaskStatus::Done] {
+        let count = counts.get(&status).copied().unwrap_or(0);
+        writeln!(out, "{}: {}", status, count)?;
+    }
+    Ok(())
+}
+
+/// Handles the "schema" command to print the JSON Schema of the task file.
+///
+/// # Arguments
+///
+/// * `out` - The writer receiving the output of the command.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_schema_command(out: &mut impl Write) -> Result<(), TaskError> {
+    let schema = serde_json::to_string_pretty(&task_repository::json_schema())
+        .map_err(|err| format!("Failed to render the schema: {}", err))?;
+    ignore_broken_pipe(writeln!(out, "{}", schema))
+}
+
+/// Handles the "export" command to print the task list in another format.
+///
+/// The format is `--format markdown`, the default, or `--format ical`.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+/// * `repo` - A reference to the `TaskRepository`.
+/// * `out` - The writer receiving the output of the command.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_export_command(
+    args: &[String],
+    repo: &TaskRepository,
+    out: &mut impl Write,
+) -> Result<(), TaskError> {
+    let mut format = None;
+    let mut remaining = args.iter().skip(2);
+    while let Some(arg) = remaining.next() {
+        match arg.as_str() {
+            "--format" => {
+                let value = remaining
+                    .next()
+                    .ok_or(TaskError::MissingArgument("format for --format"))?;
+                set_once(&mut format, value.as_str(), arg)?;
+            }
+            _ => return Err(TaskError::Invalid(format!("Unexpected argument: {}", arg))),
+        }
+    }
+    match format.unwrap_or("markdown") {
+        "markdown" => ignore_broken_pipe(write!(out, "{}", export_markdown(repo))),
+        "ical" => ignore_broken_pipe(write!(out, "{}", export_ical(repo))),
+        format => Err(TaskError::Invalid(format!(
+            "Unsupported export format: {}",
+            format
+        ))),
+    }
+}
+
+/// Handles the "dump" command to write the whole repository as JSON.
+///
+/// The JSON goes to the output, or to the file given with `--out <path>`.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+/// * `repo` - A reference to the `TaskRepository`.
+/// * `out` - The writer receiving the output of the command.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_dump_command(
+    args: &[String],
+    repo: &TaskRepository,
+    out: &mut impl Write,
+) -> Result<(), TaskError> {
+    let mut out_path = None;
+    let mut remaining = args.iter().skip(2);
+    while let Some(arg) = remaining.next() {
+        match arg.as_str() {
+            "--out" => {
+                let value = remaining
+                    .next()
+                    .ok_or(TaskError::MissingArgument("path for --out"))?;
+                set_once(&mut out_path, value, arg)?;
+            }
+            _ => return Err(TaskError::Invalid(format!("Unexpected argument: {}", arg))),
+        }
+    }
+    let Some(path) = out_path else {
+        return ignore_broken_pipe(
+            task_repository::dump_repository(repo, &mut *out)
+                .map_err(io::Error::from)
+                .and_then(|()| writeln!(out)),
+        );
+    };
+    let write_error = |err| TaskError::io(format_args!("Failed to write dump file {}", path), err);
+    let mut file = fs::File::create(path).map_err(write_error)?;
+    task_repository::dump_repository(repo, &mut file).map_err(|err| write_error(err.into()))?;
+    writeln!(file).map_err(write_error)
+}
+
+/// Handles the "restore" command to replace every task with those of a dump.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `ctx` - The `Context` the command runs in.
+/// * `out` - The writer receiving the output of the command.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_restore_command(
+    args: &[String],
+    repo: &mut TaskRepository,
+    ctx: &Context,
+    out: &mut impl Write,
+) -> Result<(), TaskError> {
+    let path = args
+        .get(2)
+        .ok_or(TaskError::MissingArgument("path of the dump to restore"))?;
+    let restored = task_repository::restore_dump(repo, path)?;
+    save_repository(repo, ctx)?;
+    ignore_broken_pipe(writeln!(out, "Restored {} task(s)", restored))
+}
+
+/// Renders the tasks as a Markdown checkbox list in id order, done tasks being checked.
+///
+/// # Arguments
+///
+/// * `repo` - A reference to the `TaskRepository`.
+///
+/// # Returns
+///
+/// The Markdown list, one line per task.
+fn export_markdown(repo: &TaskRepository) -> String {
+    repo.tasks()
+        .map(|task| {
+            let checkbox = if task.status == TaskStatus::Done {
+                "[x]"
+            } else {
+                "[ ]"
+            };
+            format!("- {} #{} {}\n", checkbox, task.id, task.description)
+        })
+        .collect()
+}
+
+/// Renders the tasks having a due date as an iCalendar of to-dos, in id order.
+///
+/// The lines end with CRLF as the format requires, and the dates are in UTC.
+///
+/// # Arguments
+///
+/// * `repo` - A reference to the `TaskRepository`.
+///
+/// # Returns
+///
+/// The calendar, with one `VTODO` per task due, empty of them if none is.
+fn export_ical(repo: &TaskRepository) -> String {
+    let utc = |date: DateTime<Local>| date.to_utc().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut calendar = String::from("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//tasklist//EN\r\n");
+    for task in repo.tasks() {
+        let Some(due_date) = task.due_date else {
+            continue;
+        };
+        let status = match task.status {
+            TaskStatus::Todo => "NEEDS-ACTION",
+            TaskStatus::InProgress => "IN-PROCESS",
+            TaskStatus::Done => "COMPLETED",
+        };
+        calendar.push_str(&format!(
+            "BEGIN:VTODO\r\nUID:task-{}@tasklist\r\nDTSTAMP:{}\r\nSUMMARY:{}\r\nDUE:{}\r\nSTATUS:{}\r\nEND:VTODO\r\n",
+            task.id,
+            utc(task.updated_at),
+            escape_ical_text(&task.description),
+            utc(due_date),
+            status
+        ));
+    }
+    calendar.push_str("END:VCALENDAR\r\n");
+    calendar
+}
+
+/// Escapes the characters having a meaning in an iCalendar text value.
+///
+/// # Arguments
+///
+/// * `text` - The text to escape.
+///
+/// # Returns
+///
+/// The text with its backslashes, semicolons and commas escaped and its line breaks as `\n`.
+fn escape_ical_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Handles the "search" command to display tasks whose description contains a query.
+///
+/// # Arguments
+///
+/// * `args` - A slice of command-line arguments.
+/// * `repo` - A reference to the `TaskRepository`.
+/// * `out` - The writer receiving the output of the command.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_search_command(
+    args: &[String],
+    repo: &TaskRepository,
+    out: &mut impl Write,
+) -> Result<(), TaskError> {
+    let query = args
+        .get(2)
+        .ok_or(TaskError::MissingArgument("search query"))?;
+    let task_list = repo.search(query);
+    ignore_broken_pipe(if task_list.is_empty() {
+        writeln!(out, "No matching tasks")
+    } else {
+        task_list
+            .into_iter()
+            .try_for_each(|task| print_task(task, out))
+    })
+}
+
+/// The content of the `--json-cache` file: the output of `list --json` for a task file.
+#[derive(Serialize, Deserialize)]
+struct JsonCache {
+    /// The modification time of the task file the output was made from.
+    source_modified: SystemTime,
+    /// The size of the task file the output was made from.
+    source_len: u64,
+    /// The output of `list --json`.
+    json: String,
+}
+
+/// Returns the output of `list --json`, from the cache file if the task file did not change.
+///
+/// The task file is taken to be unchanged while its modification time and size are those the
+/// cache was made with. Otherwise the tasks are loaded and listed, and the cache is rewritten.
+/// A cache file that cannot be read is made again.
+///
+/// # Arguments
+///
+/// * `repo` - Loads the tasks, only called when the cache cannot be used.
+/// * `source_meta` - The metadata of the task file.
+/// * `cache_file` - The path of the cache file.
+///
+/// # Returns
+///
+/// The output of `list --json`, or an error message if the tasks cannot be loaded or the
+/// cache file cannot be written.
+fn cached_json(
+    repo: impl FnOnce() -> Result<TaskRepository, TaskError>,
+    source_meta: &fs::Metadata,
+    cache_file: &Path,
+) -> Result<String, TaskError> {
+    let source_modified = source_meta.modified().map_err(|err| {
+        TaskError::io("Failed to read the modification time of the task file", err)
+    })?;
+    let source_len = source_meta.len();
+    let cached = fs::read(cache_file)
+        .ok()
+        .and_then(|content| serde_json::from_slice::<JsonCache>(&content).ok())
+        .filter(|cache| cache.source_modified == source_modified && cache.source_len == source_len);
+    if let Some(cache) = cached {
+        return Ok(cache.json);
+    }
+    let options = ListOptions {
+        json: true,
+        ..ListOptions::default()
+    };
+    let mut json = Vec::new();
+    print_listed_tasks(&repo()?, &options, Locale::default(), &mut json)
+        .map_err(|err| TaskError::io("Failed to list the tasks", err))?;
+    let cache = JsonCache {
+        source_modified,
+        source_len,
+        json: String::from_utf8_lossy(&json).into_owned(),
+    };
+    fs::write(cache_file, serde_json::to_string(&cache)?).map_err(|err| {
+        TaskError::io(
+            format_args!("Failed to write cache file {}", cache_file.display()),
+            err,
+        )
+    })?;
+    Ok(cache.json)
+}
+
+/// Prints the tasks selected by the "list" options, or a message saying why there are none.
+///
+/// In JSON mode, the tasks are printed as an array, empty if none matches.
+/// In ids mode, only their ids are printed, one per line, and nothing if none matches.
+/// With `--limit` or `--offset`, only a page of the sorted tasks is printed, followed by
+/// the range of positions it covers except in JSON and ids modes.
+///
+/// # Arguments
+///
+/// * `repo` - A reference to the `TaskRepository`.
+/// * `options` - The parsed `ListOptions` selecting and ordering the tasks.
+/// * `locale` - The language of the headers and of the stale marker.
+/// * `out` - The writer receiving the output.
+fn print_listed_tasks(
+    repo: &TaskRepository,
+    options: &ListOptions,
+    locale: Locale,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let now = Local::now();
+    let mut task_list: Vec<&Task> = repo
+        .tasks()
+        .filter(|task| options.matches(task, now))
+        .collect();
+    sort_tasks(&mut task_list, options.order);
+    let total = task_list.len();
+    let start = options.offset.unwrap_or(0).min(total);
+    let end = options
+        .limit
+        .map_or(total, |limit| start.saturating_add(limit).min(total));
+    let page = &task_list[start..end];
+    if options.utc {
+        let page: Vec<UtcTask> = page.iter().map(|task| UtcTask::from(*task)).collect();
+        serde_json::to_writer_pretty(&mut *out, &page)?;
+        return writeln!(out);
+    }
+    if options.json {
+        serde_json::to_writer_pretty(&mut *out, page)?;
+        return writeln!(out);
+    }
+    if options.ids {
+        return page
+            .iter()
+            .try_for_each(|task| writeln!(out, "{}", task.id));
+    }
+    if page.is_empty() {
+        if options.compact_empty {
+            return Ok(());
+        }
+        if total > 0 {
+            return writeln!(out, "No task at offset {} of {}", start, total);
+        }
+        return writeln!(out, "{}", options.empty_message());
+    }
+    if options.group_by_due {
+        print_grouped_by_due(page, options.stale_after, locale, Local::now(), out)?;
+    } else {
+        page.iter()
+            .try_for_each(|task| print_listed_task(task, options.stale_after, locale, out))?;
+    }
+    if options.limit.is_some() || options.offset.is_some() {
+        writeln!(out, "Showing {}-{} of {}", start, end - 1, total)?;
+    }
+    Ok(())
+}
+
+/// Prints tasks under a header for each `DueBucket` holding some of them.
+///
+/// # Arguments
+///
+/// * `tasks` - The tasks to print, in the order they are printed within a bucket.
+/// * `stale_after` - An optional age after which a task is marked as stale.
+/// * `locale` - The language of the headers and of the stale marker.
+/// * `now` - The reference time the due dates are compared to.
+/// * `out` - The writer receiving the output.
+fn print_grouped_by_due(
+    tasks: &[&Task],
+    stale_after: Option<TimeDelta>,
+    locale: Locale,
+    now: DateTime<Local>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    for bucket in DueBucket::ALL {
+        let mut members = tasks
+            .iter()
+            .filter(|task| DueBucket::of(task.due_date, now) == bucket)
+            .peekable();
+        if members.peek().is_none() {
+            continue;
+        }
+        writeln!(out, "{}:", bucket.header(locale))?;
+        members.try_for_each(|task| print_listed_task(task, stale_after, locale, out))?;
+    }
+    Ok(())
+}
+
+/// Sorts tasks in the given order, tasks with equal sort fields being ordered by id.
+///
+/// # Arguments
+///
+/// * `tasks` - The tasks to sort.
+/// * `order` - The order to sort them in.
+fn sort_tasks(tasks: &mut [&Task], order: ListOrder) {
+    match order.field {
+        SortField::Id => tasks.sort_by_key(|task| task.id),
+        SortField::Created => tasks.sort_by_key(|task| (task.created_at, task.id)),
+        SortField::Updated => tasks.sort_by_key(|task| (task.updated_at, task.id)),
+        SortField::Status => tasks.sort_by_key(|task| (status_rank(&task.status), task.id)),
+    }
+    if order.reverse {
+        tasks.reverse();
+    }
+}
+
+/// Gives the position of a status in the workflow, from todo to done.
+///
+/// # Arguments
+///
+/// * `status` - The status to rank.
+fn status_rank(status: &TaskStatus) -> u8 {
+    match status {
+        TaskStatus::Todo => 0,
+        TaskStatus::InProgress => 1,
+        TaskStatus::Done => 2,
+    }
+}
+
+/// Prints a single task, with its due date if it has one.
+///
+/// # Arguments
+///
+/// * `task` - A reference to the `Task` to be printed.
+/// * `out` - The writer receiving the output.
+fn print_task(task: &Task, out: &mut impl Write) -> io::Result<()> {
+    write!(
+        out,
+        "Task {}: \"{}\" {}. Priority: {}. Created at: {}. Last update: {}",
+        task.id, task.description, task.status, task.priority, task.created_at, task.updated_at
+    )?;
+    if let Some(due_date) = task.due_date {
+        write!(out, ". Due: {}", due_date)?;
+    }
+    match task.completed_at {
+        Some(completed_at) => writeln!(out, ". Completed at: {}", completed_at)?,
+        None => writeln!(out)?,
+    }
+    print_checklist(task, out)
+}
+
+/// Prints the checklist of a task, one indented line per item.
+///
+/// # Arguments
+///
+/// * `task` - A reference to the `Task` whose checklist is printed.
+/// * `out` - The writer receiving the output.
+fn print_checklist(task: &Task, out: &mut impl Write) -> io::Result<()> {
+    for (text, checked) in &task.checklist {
+        let mark = if *checked { 'x' } else { ' ' };
+        writeln!(out, "    [{}] {}", mark, text)?;
+    }
+    Ok(())
+}
+
+/// Prints a single task as part of a listing, marking it if it is stale.
+///
+/// # Arguments
+///
+/// * `task` - A reference to the `Task` to be printed.
+/// * `stale_after` - An optional age after which the task is marked as stale.
+/// * `locale` - The language of the stale marker.
+/// * `out` - The writer receiving the output.
+fn print_listed_task(
+    task: &Task,
+    stale_after: Option<TimeDelta>,
+    locale: Locale,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    match stale_after {
+        Some(threshold) if is_stale(task, threshold, Local::now()) => {
+            write!(out, "{} ", locale.stale_marker())?;
+            print_task(task, out)
+        }
+        _ => print_task(task, out),
+    }
+}
+
+/// Tells whether a task has not been updated for longer than the given threshold.
+///
+/// # Arguments
+///
+/// * `task` - A reference to the `Task` to check.
+/// * `threshold` - The age after which a task is stale.
+/// * `now` - The reference time the age is computed from.
+fn is_stale(task: &Task, threshold: TimeDelta, now: DateTime<Local>) -> bool {
+    now - task.updated_at > threshold
+}
+
+/// The fields of a task to be added, as given to the "add" command.
+#[derive(Debug)]
+struct NewTask {
+    /// A string describing the new task.
+    description: String,
+    /// The creation time of the new task.
+    created_at: DateTime<Local>,
+    /// An optional ID for the new task, the next free one is used otherwise.
+    id: Option<i32>,
+    /// The priority of the new task.
+    priority: Priority,
+    /// The tags of the new task, duplicates being ignored.
+    tags: Vec<String>,
+}
+
+impl NewTask {
+    /// Describes a new task created now, with no chosen id, `Medium` priority and no tag.
+    ///
+    /// # Arguments
+    ///
+    /// * `description` - A string describing the new task.
+    #[cfg(test)]
+    fn new(description: &str) -> Self {
+        NewTask {
+            description: description.to_string(),
+            created_at: Local::now(),
+            id: None,
+            priority: Priority::default(),
+            tags: Vec::new(),
+        }
+    }
+}
+
+/// Adds a new task to the repository.
+///
+/// # Arguments
+///
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `new_task` - The fields of the task to add.
+/// * `ctx` - The `Context` the command runs in.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn add_task(repo: &mut TaskRepository, new_task: NewTask, ctx: &Context) -> Result<(), TaskError> {
+    let id = match new_task.id {
+        Some(id) => {
+            repo.new_task_with_id_at(id, new_task.description, new_task.created_at)?;
+            id
+        }
+        None => repo.new_task_at(new_task.description, new_task.created_at),
+    };
+    let tags: Vec<Tag> = new_task
+        .tags
+        .iter()
+        .map(|tag| repo.intern_tag(tag))
+        .collect();
+    if let Some(task) = repo.task(id) {
+        task.priority = new_task.priority;
+        for tag in tags {
+            if !task.tags.contains(&tag) {
+                task.tags.push(tag);
+            }
+        }
+    }
+    save_repository(repo, ctx)
+}
+
+/// Asks for the confirmation of the deletion of each existing task.
+///
+/// # Arguments
+///
+/// * `repo` - A reference to the `TaskRepository`.
+/// * `task_ids` - The IDs of the tasks to be deleted.
+/// * `input` - The reader providing the answers.
+/// * `out` - The writer receiving the prompts.
+///
+/// # Returns
+///
+/// The IDs of the confirmed tasks, followed by those that did not match any task so that they
+/// can still be reported, or an error message if the input cannot be read.
+fn confirm_deletions(
+    repo: &TaskRepository,
+    task_ids: Vec<i32>,
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+) -> Result<Vec<i32>, TaskError> {
+    let mut kept_ids = Vec::new();
+    for id in task_ids {
+        let confirmed = match repo.task_ref(id) {
+            Some(task) => {
+                let prompt = format!("Delete task {} (\"{}\")?", id, task.description);
+                confirm(&prompt, input, out)?
+            }
+            None => true,
+        };
+        if confirmed {
+            kept_ids.push(id);
+        }
+    }
+    Ok(kept_ids)
+}
+
+/// Asks a yes or no question, the answer being no unless it is `y` or `yes`.
+///
+/// # Arguments
+///
+/// * `prompt` - The question, followed by `[y/N]` when printed.
+/// * `input` - The reader providing the answer, whose end means no.
+/// * `out` - The writer receiving the prompt.
+///
+/// # Returns
+///
+/// Whether the answer is yes, or an error message if the input cannot be read.
+fn confirm(
+    prompt: &str,
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+) -> Result<bool, TaskError> {
+    ignore_broken_pipe(write!(out, "{} [y/N] ", prompt))?;
+    ignore_broken_pipe(out.flush())?;
+    let mut answer = String::new();
+    input
+        .read_line(&mut answer)
+        .map_err(|err| TaskError::io("Failed to read answer", err))?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Asks once whether to go on with a bulk deletion, unless it removes few enough tasks.
+///
+/// # Arguments
+///
+/// * `prompt` - The question, whose `{}` is replaced with the number of tasks.
+/// * `count` - The number of tasks the deletion removes.
+/// * `confirmed` - Whether `--yes` was given, so that nothing is asked.
+/// * `ctx` - The `Context` whose confirmation threshold applies.
+/// * `input` - The reader providing the answer.
+/// * `out` - The writer receiving the prompt.
+///
+/// # Returns
+///
+/// Whether to delete the tasks, or an error message if the input cannot be read.
+fn confirm_bulk(
+    prompt: &str,
+    count: usize,
+    confirmed: bool,
+    ctx: &Context,
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+) -> Result<bool, TaskError> {
+    if confirmed || count <= ctx.confirm_threshold {
+        return Ok(true);
+    }
+    confirm(&prompt.replace("{}", &count.to_string()), input, out)
+}
+
+/// Deletes tasks from the repository and saves it once.
+///
+/// # Arguments
+///
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `task_ids` - The IDs of the tasks to be deleted.
+/// * `ctx` - The `Context` the command runs in.
+///
+/// # Returns
+///
+/// The IDs that did not match any task, or an error message if the repository cannot be saved.
+fn delete_tasks(
+    repo: &mut TaskRepository,
+    task_ids: &[i32],
+    ctx: &Context,
+) -> Result<Vec<i32>, TaskError> {
+    let missing = task_ids
+        .iter()
+        .filter(|id| repo.delete(**id).is_none())
+        .copied()
+        .collect();
+    save_repository(repo, ctx)?;
+    Ok(missing)
+}
+
+/// Deletes every task created longer ago than the given age and saves the repository.
+///
+/// # Arguments
+///
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `older_than` - The age past which tasks are deleted.
+/// * `now` - The reference time the age is computed from.
+/// * `ctx` - The `Context` the command runs in.
+///
+/// # Returns
+///
+/// The number of deleted tasks, or an error message if the repository cannot be saved.
+fn delete_tasks_older_than(
+    repo: &mut TaskRepository,
+    older_than: TimeDelta,
+    now: DateTime<Local>,
+    ctx: &Context,
+) -> Result<usize, TaskError> {
+    let removed = repo.retain(|task| now - task.created_at <= older_than);
+    save_repository(repo, ctx)?;
+    Ok(removed)
+}
+
+/// Finds the tasks created longer ago than the given age.
+///
+/// # Arguments
+///
+/// * `repo` - A reference to the `TaskRepository`.
+/// * `older_than` - The age past which tasks match.
+/// * `now` - The reference time the age is computed from.
+///
+/// # Returns
+///
+/// The ids of the matching tasks.
+fn tasks_older_than(
+    repo: &TaskRepository,
+    older_than: TimeDelta,
+    now: DateTime<Local>,
+) -> Vec<i32> {
+    repo.tasks()
+        .filter(|task| now - task.created_at > older_than)
+        .map(|task| task.id)
+        .collect()
+}
+
+/// Prints how many of the given tasks exist, followed by those tasks in id order.
+///
+/// # Arguments
+///
+/// * `repo` - A reference to the `TaskRepository`.
+/// * `ids` - The ids of the tasks a command would affect.
+/// * `out` - The writer receiving the output.
+fn print_matched_tasks(repo: &TaskRepository, ids: &[i32], out: &mut impl Write) -> io::Result<()> {
+    let ids: BTreeSet<i32> = ids.iter().copied().collect();
+    let matched: Vec<&Task> = ids.into_iter().filter_map(|id| repo.task_ref(id)).collect();
+    writeln!(out, "Matched {} task(s)", matched.len())?;
+    matched
+        .into_iter()
+        .try_for_each(|task| print_task(task, out))
+}
+
+/// Updates the description of a task.
+///
+/// # Arguments
+///
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `id` - The ID of the task to be updated.
+/// * `new_desc` - The new description for the task.
+/// * `ctx` - The `Context` the command runs in.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn update_task(
+    repo: &mut TaskRepository,
+    id: i32,
+    new_desc: String,
+    ctx: &Context,
+) -> Result<(), TaskError> {
+    repo.set_description(id, new_desc)?;
+    save_repository(repo, ctx)
+}
+
+/// Handles the "undo" command to bring back the tasks as they were before the last save.
+///
+/// Only one level of undo is kept: undoing again brings back the state that was undone.
+///
+/// # Arguments
+///
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `ctx` - The `Context` the command runs in.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn handle_undo_command(repo: &mut TaskRepository, ctx: &Context) -> Result<(), TaskError> {
+    task_repository::restore_backup(repo, &ctx.task_file)?;
+    save_repository(repo, ctx)
+}
+
+/// Saves the current state of the task repository to the task file of the context.
+///
+/// In read-only mode, nothing is written and an error is returned instead. The task file is
+/// locked while saving, and is not replaced if another command changed it since it was read.
+///
+/// # Arguments
+///
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `ctx` - The `Context` the command runs in, counting the saves.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn save_repository(repo: &mut TaskRepository, ctx: &Context) -> Result<(), TaskError> {
+    if ctx.readonly {
+        return Err(TaskError::Invalid("Repository is read-only".to_string()));
+    }
+    let task_file = ctx.task_file.display();
+    if ctx.create_parent_dirs {
+        if let Some(parent) = ctx.task_file.parent() {
+            fs::create_dir_all(parent).map_err(|err| {
+                TaskError::io(format_args!("Failed to save tasks to {}", task_file), err)
+            })?;
+        }
+    }
+    // Held while checking and replacing the file, so that no other command saves in between.
+    let _lock = task_repository::lock_file(&ctx.task_file)?;
+    if task_repository::file_version(&ctx.task_file)? != ctx.loaded_version.get() {
+        return Err(TaskError::Invalid(format!(
+            "Task file {} was changed by another command since it was read, run the command again",
+            task_file
+        )));
+    }
+    let start = Instant::now();
+    let saved = task_repository::save_repository(repo, &ctx.task_file);
+    ctx.save_time.set(ctx.save_time.get() + start.elapsed());
+    let version = saved
+        .map_err(|err| TaskError::io(format_args!("Failed to save tasks to {}", task_file), err))?;
+    ctx.loaded_version.set(version);
+    ctx.saves.set(ctx.saves.get() + 1);
+    Ok(())
+}
+
+/// Marks a task as in progress.
+///
+/// # Arguments
+///
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `id` - The ID of the task to be marked as in progress.
+/// * `ctx` - The `Context` the command runs in.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn mark_in_progress(repo: &mut TaskRepository, id: i32, ctx: &Context) -> Result<(), TaskError> {
+    repo.set_status(id, TaskStatus::InProgress)?;
+    save_repository(repo, ctx)
+}
+
+/// Converts the result of writing command output into the result of the command.
+///
+/// Output being closed early, e.g. by `tasklist list | head`, is not an error: the reader
+/// simply went away, so the command still succeeds.
+///
+/// # Arguments
+///
+/// * `result` - The result of writing the output.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn ignore_broken_pipe(result: io::Result<()>) -> Result<(), TaskError> {
+    match result {
+        Err(err) if err.kind() != ErrorKind::BrokenPipe => {
+            Err(TaskError::io("Failed to write output", err))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Marks a task as todo.
+///
+/// # Arguments
+///
+/// * `repo` - A mutable reference to the `TaskRepository`.
+/// * `id` - The ID of the task to be marked as todo.
+/// * `ctx` - The `Context` the command runs in.
+///
+/// # Returns
+///
+/// A `Result` indicating the success or failure of the operation.
+fn mark_todo(repo: &mut TaskRepository, id: i32, ctx: &Context) -> Result<(), TaskError> {
+    repo.set_status(id, TaskStatus::Todo)?;
+    save_repository(repo, ctx)
+}
+
+/// Parses a task id from a command-line argument.
+///
+/// # Arguments
+///
+/// * `arg` - The argument to parse.
+///
+/// # Returns
+///
+/// The parsed id, or an error message if the argument is not a number.
+fn parse_id(arg: &str) -> Result<i32, TaskError> {
+    arg.parse::<i32>()
+        .map_err(|_| TaskError::InvalidId(arg.to_string()))
+}
+
+/// Parses an ISO-8601 timestamp from a command-line argument.
+///
+/// Timestamps with an offset (e.g. `2024-01-01T10:00:00+02:00`) are converted to local time,
+/// timestamps without one (e.g. `2024-01-01T10:00:00`) are read as local time.
+///
+/// # Arguments
+///
+/// * `arg` - The argument to parse.
+///
+/// # Returns
+///
+/// The parsed timestamp, or an error message if the argument is not a valid timestamp.
+fn parse_timestamp(arg: &str) -> Result<DateTime<Local>, TaskError> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(arg) {
+        return Ok(timestamp.with_timezone(&Local));
+    }
+    NaiveDateTime::parse_from_str(arg, "%Y-%m-%dT%H:%M:%S")
+        .ok()
+        .and_then(|timestamp| timestamp.and_local_timezone(Local).earliest())
+        .ok_or_else(|| TaskError::Invalid(format!("Invalid timestamp: {}", arg)))
+}
+
+/// Parses a due date from a command-line argument.
+///
+/// Besides the timestamps accepted by `parse_timestamp`, a plain `YYYY-MM-DD` date is accepted
+/// and read as the end of that day in local time, so that a task is not overdue on its due day.
+/// So is the name of a day of the week in the given locale, for the next such day.
+///
+/// # Arguments
+///
+/// * `arg` - The argument to parse.
+/// * `locale` - The language of the weekday names.
+///
+/// # Returns
+///
+/// The parsed due date, or an error message if the argument is not a valid date.
+fn parse_due_date(arg: &str, locale: Locale) -> Result<DateTime<Local>, TaskError> {
+    if let Ok(timestamp) = parse_timestamp(arg) {
+        return Ok(timestamp);
+    }
+    match locale.parse_weekday(arg) {
+        Some(weekday) => next_weekday(weekday, Local::now().date_naive()),
+        None => NaiveDate::parse_from_str(arg, "%Y-%m-%d").ok(),
+    }
+    .and_then(end_of_day)
+    .ok_or_else(|| TaskError::Invalid(format!("Invalid due date: {}", arg)))
+}
+
+/// Finds the next day falling on a day of the week, which is today if it falls on it.
+///
+/// # Arguments
+///
+/// * `weekday` - The day of the week.
+/// * `today` - The day the search starts from.
+///
+/// # Returns
+///
+/// The found day, or `None` past the last representable date.
+fn next_weekday(weekday: Weekday, today: NaiveDate) -> Option<NaiveDate> {
+    let days_ahead = weekday.days_since(today.weekday());
+    today.checked_add_days(chrono::Days::new(days_ahead.into()))
+}
+
+/// Returns the last second of a day in local time.
+///
+/// # Arguments
+///
+/// * `date` - The day.
+///
+/// # Returns
+///
+/// The end of the day, or `None` if it does not exist in the local time zone.
+fn end_of_day(date: NaiveDate) -> Option<DateTime<Local>> {
+    date.and_hms_opt(23, 59, 59)
+        .and_then(|timestamp| timestamp.and_local_timezone(Local).earliest())
+}
+
+/// Parses a task status from a command-line argument.
+///
+/// # Arguments
+///
+/// * `arg` - The argument to parse, one of `todo`, `in-progress` or `done` in any case.
+///   In progress can also be spelled `inprogress`, `in_progress` or `wip`.
+///
+/// # Returns
+///
+/// The matching status, or `None` if the argument is not a known status.
+fn parse_status(arg: &str) -> Option<TaskStatus> {
+    arg.parse().ok()
+}
+
+/// Parses the layout given to `--layout`.
+///
+/// # Arguments
+///
+/// * `arg` - The command-line argument, `wrapped` or `array`.
+///
+/// # Returns
+///
+/// The parsed `FileLayout`, or an error message for an unknown layout.
+fn parse_layout(arg: &str) -> Result<FileLayout, TaskError> {
+    match arg {
+        "wrapped" => Ok(FileLayout::Wrapped),
+        "array" => Ok(FileLayout::Array),
+        _ => Err(TaskError::Invalid(format!("Unknown layout: {}", arg))),
+    }
+}
+
+/// Parses a task priority from a command-line argument.
+///
+/// # Arguments
+///
+/// * `arg` - The command-line argument, one of `low`, `medium` or `high`.
+///
+/// # Returns
+///
+/// The parsed `Priority`, or an error message for an unknown priority.
+fn parse_priority(arg: &str) -> Result<Priority, TaskError> {
+    match arg {
+        "low" => Ok(Priority::Low),
+        "medium" => Ok(Priority::Medium),
+        "high" => Ok(Priority::High),
+        _ => Err(TaskError::Invalid(format!("Unknown priority: {}", arg))),
+    }
+}
+
+/// Parses the policy given to `import --on-conflict`.
+///
+/// # Arguments
+///
+/// * `arg` - The command-line argument, one of `skip`, `rename` or `replace`.
+///
+/// # Returns
+///
+/// The parsed `ImportConflict`, or an error message for an unknown policy.
+fn parse_import_conflict(arg: &str) -> Result<ImportConflict, TaskError> {
+    match arg {
+        "skip" => Ok(ImportConflict::Skip),
+        "rename" => Ok(ImportConflict::Rename),
+        "replace" => Ok(ImportConflict::Replace),
+        _ => Err(TaskError::Invalid(format!(
+            "Unknown conflict policy: {}",
+            arg
+        ))),
+    }
+}
+
+/// Parses the field given to `list --sort`.
+///
+/// # Arguments
+///
+/// * `arg` - The command-line argument, one of `id`, `created`, `updated` or `status`.
+///
+/// # Returns
+///
+/// The parsed `SortField`, or an error message for an unknown field.
+fn parse_sort_field(arg: &str) -> Result<SortField, TaskError> {
+    match arg {
+        "id" => Ok(SortField::Id),
+        "created" => Ok(SortField::Created),
+        "updated" => Ok(SortField::Updated),
+        "status" => Ok(SortField::Status),
+        _ => Err(TaskError::Invalid(format!("Unknown sort field: {}", arg))),
+    }
+}
+
+/// Parses a duration such as `30d` from a command-line argument.
+///
+/// The supported units are `m` (minutes), `h` (hours), `d` (days) and `w` (weeks).
+///
+/// # Arguments
+///
+/// * `arg` - The argument to parse.
+///
+/// # Returns
+///
+/// The parsed duration, or an error message if the argument is not a valid duration.
+fn parse_duration(arg: &str) -> Result<TimeDelta, TaskError> {
+    let invalid_duration = || TaskError::Invalid(format!("Invalid duration: {}", arg));
+    let unit_start = arg.len() - arg.chars().last().map_or(0, char::len_utf8);
+    let amount = arg[..unit_start]
+        .parse::<i64>()
+        .ok()
+        .filter(|amount| *amount >= 0)
+        .ok_or_else(invalid_duration)?;
+    match &arg[unit_start..] {
+        "m" => TimeDelta::try_minutes(amount),
+        "h" => TimeDelta::try_hours(amount),
+        "d" => TimeDelta::try_days(amount),
+        "w" => TimeDelta::try_weeks(amount),
+        _ => None,
+    }
+    .ok_or_else(invalid_duration)
+}
+
+/// The ids given to a command, split between those of existing tasks and the missing ones.
+#[derive(Debug, PartialEq)]
+struct IdList {
+    /// The ids of the existing tasks, in the order they were given.
+    ids: Vec<i32>,
+    /// The ids matching no task, in the order they were given, the consecutive ones of a range
+    /// being joined into a sub-range such as `5-7`.
+    missing: Vec<String>,
+}
+
+/// Parses a list of task ids, expanding inclusive ranges such as `1-5`.
+///
+/// A range only visits the existing tasks within it, so that a wide one stays cheap, and the
+/// gaps between them are reported as sub-ranges rather than one id at a time.
+///
+/// # Arguments
+///
+/// * `repo` - A reference to the `TaskRepository` the ids are looked up in.
+/// * `args` - The arguments holding ids or ranges.
+///
+/// # Returns
+///
+/// The parsed `IdList`, or an error message on the first malformed entry.
+fn parse_id_list(repo: &TaskRepository, args: &[String]) -> Result<IdList, TaskError> {
+    let mut list = IdList {
+        ids: Vec::new(),
+        missing: Vec::new(),
+    };
+    for arg in args {
+        match arg.split_once('-') {
+            Some((start, end)) if !start.is_empty() => {
+                let invalid_range = || TaskError::Invalid(format!("Invalid id range: {}", arg));
+                let start = start.parse::<i32>().map_err(|_| invalid_range())?;
+                let end = end.parse::<i32>().map_err(|_| invalid_range())?;
+                if start > end {
+                    return Err(invalid_range());
+                }
+                // The first id not yet known to exist or to be missing, if any is left.
+                let mut next = Some(start);
+                for id in repo.ids_in_range(start..=end) {
+                    if let Some(first_missing) = next.filter(|next| *next < id) {
+                        list.missing.push(format_id_range(first_missing, id - 1));
+                    }
+                    list.ids.push(id);
+                    next = id.checked_add(1);
+                }
+                if let Some(first_missing) = next.filter(|next| *next <= end) {
+                    list.missing.push(format_id_range(first_missing, end));
+                }
+            }
+            _ => {
+                let id = parse_id(arg)?;
+                match repo.task_ref(id) {
+                    Some(_) => list.ids.push(id),
+                    None => list.missing.push(id.to_string()),
+                }
+            }
+        }
+    }
+    Ok(list)
+}
+
+/// Formats an inclusive range of ids as `start-end`, or as a single id if it holds only one.
+///
+/// # Arguments
+///
+/// * `start` - The first id of the range.
+/// * `end` - The last id of the range.
+fn format_id_range(start: i32, end: i32) -> String {
+    if start == end {
+        start.to_string()
+    } else {
+        format!("{}-{}", start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, NaiveTime, TimeZone};
+    use tempfile::TempDir;
+
+    /// A writer behaving like a pipe whose reading end has been closed.
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(ErrorKind::BrokenPipe))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::Error::from(ErrorKind::BrokenPipe))
+        }
+    }
+
+    /// A writer behaving like a file on a full disk.
+    struct FullDisk;
+
+    impl Write for FullDisk {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(ErrorKind::StorageFull))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::Error::from(ErrorKind::StorageFull))
+        }
+    }
+
+    /// Creates a temporary directory and a context saving to a task file inside it.
+    ///
+    /// The directory must be kept alive for as long as the context is used.
+    fn setup() -> (TempDir, Context) {
+        let tmp_dir = TempDir::new().unwrap();
+        let ctx = Context {
+            task_file: tmp_dir.path().join("task_list.txt"),
+            create_parent_dirs: false,
+            readonly: false,
+            save_time: Cell::default(),
+            saves: Cell::default(),
+            loaded_version: Cell::default(),
+            locale: Locale::default(),
+            confirm_threshold: DEFAULT_CONFIRM_THRESHOLD,
+        };
+        (tmp_dir, ctx)
+    }
+
+    #[test]
+    fn task_added() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        add_task(&mut repo, NewTask::new("TestTask"), &ctx).unwrap();
+        let task = repo.task(1).unwrap();
+        assert_eq!(task.description, "TestTask");
+        assert_eq!(task.id, 1);
+    }
+
+    #[test]
+    fn task_added_with_backdated_creation() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        let created_at = parse_timestamp("2024-01-15T08:30:00+00:00").unwrap();
+        let new_task = NewTask {
+            created_at,
+            ..NewTask::new("Old task")
+        };
+        add_task(&mut repo, new_task, &ctx).unwrap();
+        let tmp_dir = TempDir::new().unwrap();
+        let tmp_file = tmp_dir.path().join(Path::new("tmp_file.txt"));
+        task_repository::save_repository(&mut repo, &tmp_file).unwrap();
+        let loaded_repo = task_repository::load_repository(&tmp_file).unwrap();
+        let task = loaded_repo.tasks().next().unwrap();
+        assert_eq!(task.created_at, created_at);
+        assert_eq!(task.updated_at, created_at);
+    }
+
+    #[test]
+    fn task_added_with_priority() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        for args in [
+            ["tasklist", "add", "Urgent", "high"],
+            ["tasklist", "add", "Later", "low"],
+        ] {
+            let args: Vec<String> = args.map(String::from).to_vec();
+            assert_eq!(
+                handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+                Ok(())
+            );
+        }
+        let args: Vec<String> = ["tasklist", "add", "Plop"].map(String::from).to_vec();
+        handle_command(&args, &mut repo, &ctx, &mut io::sink()).unwrap();
+        assert_eq!(repo.task(1).unwrap().priority, Priority::High);
+        assert_eq!(repo.task(2).unwrap().priority, Priority::Low);
+        assert_eq!(repo.task(3).unwrap().priority, Priority::Medium);
+
+        let args: Vec<String> = ["tasklist", "add", "Plop", "urgent"]
+            .map(String::from)
+            .to_vec();
+        handle_command(&args, &mut repo, &ctx, &mut io::sink()).unwrap();
+        assert_eq!(repo.task(4).unwrap().description, "Plop urgent");
+        assert_eq!(repo.task(4).unwrap().priority, Priority::Medium);
+    }
+
+    #[test]
+    fn task_added_from_several_words() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        for args in [
+            vec!["tasklist", "add", "deploy", "the", "release", "high"],
+            vec![
+                "tasklist", "add", "--tag", "ops", "--", "deploy", "--tag", "high",
+            ],
+            vec!["tasklist", "add", "--", "high"],
+        ] {
+            let args: Vec<String> = args.into_iter().map(String::from).collect();
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()).unwrap();
+        }
+        let task = repo.task(1).unwrap();
+        assert_eq!(task.description, "deploy the release");
+        assert_eq!(task.priority, Priority::High);
+        let task = repo.task(2).unwrap();
+        assert_eq!(task.description, "deploy --tag high");
+        assert_eq!(task.priority, Priority::Medium);
+        assert_eq!(task.tags, vec!["ops"]);
+        assert_eq!(repo.task(3).unwrap().description, "high");
+
+        let args: Vec<String> = ["tasklist", "add", "--"].map(String::from).to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Err(TaskError::MissingArgument("description to add a new task"))
+        );
+    }
+
+    #[test]
+    fn task_added_with_tags() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        let args: Vec<String> = [
+            "tasklist", "add", "Plop", "--tag", "work", "--tag", "urgent", "--tag", "work",
+        ]
+        .map(String::from)
+        .to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Ok(())
+        );
+        assert_eq!(repo.task(1).unwrap().tags, vec!["work", "urgent"]);
+    }
+
+    #[test]
+    fn tag_and_untag_commands() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        for args in [
+            ["tasklist", "tag", "1", "work"],
+            ["tasklist", "tag", "1", "work"],
+            ["tasklist", "tag", "1", "home"],
+            ["tasklist", "untag", "1", "home"],
+            ["tasklist", "untag", "1", "garden"],
+        ] {
+            let args: Vec<String> = args.map(String::from).to_vec();
+            assert_eq!(
+                handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+                Ok(())
+            );
+        }
+        assert_eq!(repo.task(1).unwrap().tags, vec!["work"]);
+
+        let args: Vec<String> = ["tasklist", "tag", "2", "work"].map(String::from).to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Err(TaskError::NotFound(2))
+        );
+    }
+
+    #[test]
+    fn checklist_commands() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        for args in [
+            ["tasklist", "check-add", "1", "First"],
+            ["tasklist", "check-add", "1", "Second"],
+            ["tasklist", "check-toggle", "1", "2"],
+        ] {
+            let args: Vec<String> = args.map(String::from).to_vec();
+            assert_eq!(
+                handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+                Ok(())
+            );
+        }
+        let loaded_repo = task_repository::load_repository(&ctx.task_file).unwrap();
+        let task = loaded_repo.tasks().next().unwrap();
+        assert_eq!(
+            task.checklist,
+            vec![("First".to_string(), false), ("Second".to_string(), true)]
+        );
+
+        let mut out = Vec::new();
+        print_task(task, &mut out).unwrap();
+        let output = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = output.lines().skip(1).collect();
+        assert_eq!(lines, ["    [ ] First", "    [x] Second"]);
+
+        for (index, err) in [
+            ("0", "Invalid checklist item: 0"),
+            ("3", "Task 1 has no checklist item 3"),
+        ] {
+            let args: Vec<String> = ["tasklist", "check-toggle", "1", index]
+                .map(String::from)
+                .to_vec();
+            assert_eq!(
+                handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+                Err(TaskError::Invalid(err.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn prioritize_applies_answers() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        for desc in ["Plop", "Plip", "Plap", "Plup", "Plep"] {
+            repo.new_task(desc.to_string());
+        }
+        repo.set_status(2, TaskStatus::Done).unwrap();
+        let mut input = "high\n\nurgent\nlow\n".as_bytes();
+        let mut out = Vec::new();
+        assert_eq!(
+            handle_prioritize_command(&mut repo, &ctx, &mut input, &mut out),
+            Ok(())
+        );
+        let priorities: Vec<Priority> = (1..=5).map(|id| repo.task(id).unwrap().priority).collect();
+        assert_eq!(
+            priorities,
+            vec![
+                Priority::High,
+                Priority::Medium,
+                Priority::Medium,
+                Priority::Low,
+                Priority::Medium
+            ]
+        );
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains("Unknown priority: urgent"));
+        let mut loaded_repo = task_repository::load_repository(&ctx.task_file).unwrap();
+        assert_eq!(loaded_repo.task(4).unwrap().priority, Priority::Low);
+    }
+
+    #[test]
+    fn import_skips_comments_and_blank_lines() {
+        let (tmp_dir, ctx) = setup();
+        let import_file = tmp_dir.path().join("import.txt");
+        fs::write(
+            &import_file,
+            "# Groceries\nBuy milk\n\n   \n  Buy bread  \n# Work\nWrite report\n",
+        )
+        .unwrap();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Existing".to_string());
+        let args: Vec<String> = ["tasklist", "import", import_file.to_str().unwrap()]
+            .map(String::from)
+            .to_vec();
+        let mut out = Vec::new();
+        assert_eq!(handle_command(&args, &mut repo, &ctx, &mut out), Ok(()));
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("Imported 3 task(s)\n"));
+        let descriptions: Vec<String> = (2..=4)
+            .map(|id| repo.task(id).unwrap().description.clone())
+            .collect();
+        assert_eq!(descriptions, ["Buy milk", "Buy bread", "Write report"]);
+        assert_eq!(repo.task_count(), 4);
+        assert_eq!(repo.count_by_status(TaskStatus::Todo), 4);
+    }
+
+    /// Imports "Plop" and "Plip" into a repository holding a done "Plop".
+    ///
+    /// # Arguments
+    ///
+    /// * `on_conflict` - The policy the lines are imported with.
+    ///
+    /// # Returns
+    ///
+    /// The number of imported lines and the repository they were imported into.
+    fn import_conflicting(on_conflict: ImportConflict) -> (usize, TaskRepository) {
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        repo.set_status(1, TaskStatus::Done).unwrap();
+        let imported = import_tasks(&mut repo, "Plop\nPlip\n", on_conflict).unwrap();
+        (imported, repo)
+    }
+
+    #[test]
+    fn import_on_conflict_skip_ignores_existing_descriptions() {
+        let (imported, mut repo) = import_conflicting(ImportConflict::Skip);
+        assert_eq!(imported, 1);
+        assert_eq!(repo.task_count(), 2);
+        assert_eq!(repo.task(1).unwrap().status, TaskStatus::Done);
+        assert_eq!(repo.task(2).unwrap().description, "Plip");
+    }
+
+    #[test]
+    fn import_on_conflict_rename_adds_new_ids() {
+        let (imported, mut repo) = import_conflicting(ImportConflict::Rename);
+        assert_eq!(imported, 2);
+        assert_eq!(repo.task_count(), 3);
+        assert_eq!(repo.task(1).unwrap().status, TaskStatus::Done);
+        assert_eq!(repo.task(2).unwrap().description, "Plop");
+        assert_eq!(repo.task(2).unwrap().status, TaskStatus::Todo);
+    }
+
+    #[test]
+    fn import_on_conflict_replace_overwrites_matching_task() {
+        let (imported, mut repo) = import_conflicting(ImportConflict::Replace);
+        assert_eq!(imported, 2);
+        assert_eq!(repo.task_count(), 2);
+        assert_eq!(repo.task(1).unwrap().description, "Plop");
+        assert_eq!(repo.task(1).unwrap().status, TaskStatus::Todo);
+        assert_eq!(repo.task(1).unwrap().completed_at, None);
+        assert_eq!(repo.task(2).unwrap().description, "Plip");
+    }
+
+    #[test]
+    fn import_on_conflict_option_parsed() {
+        let (tmp_dir, ctx) = setup();
+        let import_file = tmp_dir.path().join("import.txt");
+        fs::write(&import_file, "Plop\n").unwrap();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        let path = import_file.to_str().unwrap();
+        let args: Vec<String> = ["tasklist", "import", "--on-conflict", "skip", path]
+            .map(String::from)
+            .to_vec();
+        let mut out = Vec::new();
+        assert_eq!(handle_command(&args, &mut repo, &ctx, &mut out), Ok(()));
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("Imported 0 task(s)\n"));
+        assert_eq!(repo.task_count(), 1);
+
+        let args: Vec<String> = ["tasklist", "import", path, "--on-conflict", "merge"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Err(TaskError::Invalid(
+                "Unknown conflict policy: merge".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn purge_removes_done_tasks() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        repo.new_task("Plap".to_string());
+        repo.set_status(2, TaskStatus::Done).unwrap();
+        let args: Vec<String> = ["tasklist", "purge"].map(String::from).to_vec();
+        let mut out = Vec::new();
+        assert_eq!(handle_command(&args, &mut repo, &ctx, &mut out), Ok(()));
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("Removed 1 completed tasks\n"));
+        let loaded_repo = task_repository::load_repository(&ctx.task_file).unwrap();
+        assert_eq!(loaded_repo.task_count(), 1);
+        assert_eq!(loaded_repo.count_by_status(TaskStatus::Done), 0);
+    }
+
+    #[test]
+    fn purge_above_threshold_asks_unless_yes() {
+        let (_tmp_dir, mut ctx) = setup();
+        let mut repo = TaskRepository::default();
+        for i in 1..=3 {
+            repo.new_task(format!("Task {}", i));
+            repo.set_status(i, TaskStatus::Done).unwrap();
+        }
+        ctx.confirm_threshold = 2;
+        let args: Vec<String> = ["tasklist", "purge"].map(String::from).to_vec();
+        let mut out = Vec::new();
+        let mut input = "no\n".as_bytes();
+        assert_eq!(
+            handle_purge_command(&args, &mut repo, &ctx, &mut input, &mut out),
+            Ok(())
+        );
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Delete 3 done task(s)? [y/N] Nothing deleted\n"
+        );
+        assert_eq!(repo.task_count(), 3);
+
+        let args: Vec<String> = ["tasklist", "purge", "--yes"].map(String::from).to_vec();
+        let mut out = Vec::new();
+        assert_eq!(
+            handle_purge_command(&args, &mut repo, &ctx, &mut io::empty(), &mut out),
+            Ok(())
+        );
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("Removed 3 completed tasks\n"));
+        assert_eq!(repo.task_count(), 0);
+    }
+
+    #[test]
+    fn confirm_threshold_option() {
+        let args: Vec<String> = ["tasklist", "--confirm-threshold", "10", "purge"]
+            .map(String::from)
+            .to_vec();
+        let (options, command_args) = parse_global_options(&args).unwrap();
+        assert_eq!(options.confirm_threshold, Some(10));
+        assert_eq!(command_args, ["tasklist", "purge"].map(String::from));
+
+        let args: Vec<String> = ["tasklist", "--confirm-threshold", "many", "purge"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            parse_global_options(&args).map(|_| ()),
+            Err(TaskError::Invalid(
+                "Invalid confirmation threshold: many".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn renumber_saves_contiguous_ids() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        for desc in ["Plop", "Plap", "Plip"] {
+            repo.new_task(desc.to_string());
+        }
+        repo.delete(2);
+        let args: Vec<String> = ["tasklist", "renumber"].map(String::from).to_vec();
+        let mut out = Vec::new();
+        assert_eq!(handle_command(&args, &mut repo, &ctx, &mut out), Ok(()));
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("Renumbered 1 tasks\n"));
+        let loaded_repo = task_repository::load_repository(&ctx.task_file).unwrap();
+        let descriptions: Vec<(i32, &str)> = loaded_repo
+            .tasks()
+            .map(|task| (task.id, task.description.as_str()))
+            .collect();
+        assert_eq!(descriptions, vec![(1, "Plop"), (2, "Plip")]);
+    }
+
+    #[test]
+    fn undo_brings_back_deleted_task() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        for args in [
+            vec!["tasklist", "add", "Plop"],
+            vec!["tasklist", "delete", "1", "--yes"],
+        ] {
+            let args: Vec<String> = args.into_iter().map(String::from).collect();
+            assert_eq!(
+                handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+                Ok(())
+            );
+        }
+        assert_eq!(repo.task_count(), 0);
+
+        let mut repo = task_repository::load_repository(&ctx.task_file).unwrap();
+        let args: Vec<String> = ["tasklist", "undo"].map(String::from).to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Ok(())
+        );
+        let mut loaded_repo = task_repository::load_repository(&ctx.task_file).unwrap();
+        assert_eq!(loaded_repo.task(1).unwrap().description, "Plop");
+    }
+
+    #[test]
+    fn undo_without_history_is_an_error() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        let args: Vec<String> = ["tasklist", "undo"].map(String::from).to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Err(TaskError::Invalid("Nothing to undo".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_status_accepts_variants() {
+        for arg in ["todo", "Todo", "TODO"] {
+            assert_eq!(parse_status(arg), Some(TaskStatus::Todo), "{}", arg);
+        }
+        for arg in [
+            "in-progress",
+            "In-Progress",
+            "inprogress",
+            "in_progress",
+            "wip",
+            "WIP",
+        ] {
+            assert_eq!(parse_status(arg), Some(TaskStatus::InProgress), "{}", arg);
+        }
+        for arg in ["done", "Done", "DONE"] {
+            assert_eq!(parse_status(arg), Some(TaskStatus::Done), "{}", arg);
+        }
+        for arg in ["", "doing", "in progress", "to-do"] {
+            assert_eq!(parse_status(arg), None, "{}", arg);
+        }
+    }
+
+    #[test]
+    fn list_ids_prints_one_id_per_line() {
+        let mut repo = TaskRepository::default();
+        for desc in ["Plop", "Plip", "Plap", "Plup"] {
+            repo.new_task(desc.to_string());
+        }
+        repo.set_status(2, TaskStatus::Done).unwrap();
+        let list = |repo: &TaskRepository, extra_args: &[&str]| {
+            let args: Vec<String> = ["tasklist", "list"]
+                .iter()
+                .chain(extra_args)
+                .map(|arg| arg.to_string())
+                .collect();
+            let mut out = Vec::new();
+            handle_list_command(&args, repo, Locale::English, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(list(&repo, &["todo", "--ids"]), "1\n3\n4\n");
+        assert_eq!(list(&repo, &["--ids", "--reverse"]), "4\n3\n2\n1\n");
+        assert_eq!(list(&repo, &["in-progress", "--ids"]), "");
+        assert_eq!(list(&TaskRepository::default(), &["--ids"]), "");
+    }
+
+    #[test]
+    fn due_buckets() {
+        // A Wednesday.
+        let now = parse_timestamp("2024-05-15T12:00:00").unwrap();
+        for (due_date, bucket) in [
+            (Some("2024-05-01T10:00:00"), DueBucket::Overdue),
+            (Some("2024-05-15T11:59:59"), DueBucket::Overdue),
+            (Some("2024-05-15T12:00:00"), DueBucket::Today),
+            (Some("2024-05-15T23:59:59"), DueBucket::Today),
+            (Some("2024-05-16T09:00:00"), DueBucket::ThisWeek),
+            (Some("2024-05-19T23:59:59"), DueBucket::ThisWeek),
+            (Some("2024-05-20T00:00:00"), DueBucket::Later),
+            (Some("2025-05-15T12:00:00"), DueBucket::Later),
+            (None, DueBucket::NoDueDate),
+        ] {
+            let due_date = due_date.map(|due_date| parse_timestamp(due_date).unwrap());
+            assert_eq!(DueBucket::of(due_date, now), bucket, "{:?}", due_date);
+        }
+    }
+
+    #[test]
+    fn list_grouped_by_due() {
+        let now = parse_timestamp("2024-05-15T12:00:00").unwrap();
+        let mut repo = TaskRepository::default();
+        for (desc, due_date) in [
+            ("Later", Some("2024-06-01")),
+            ("None", None),
+            ("Late", Some("2024-05-01")),
+            ("Friday", Some("2024-05-17")),
+            ("Also late", Some("2024-05-14")),
+        ] {
+            let id = repo.new_task_at(desc.to_string(), now);
+            let due_date =
+                due_date.map(|due_date| parse_due_date(due_date, Locale::English).unwrap());
+            repo.set_due_date(id, due_date).unwrap();
+        }
+        let tasks: Vec<&Task> = repo.tasks().collect();
+        let mut out = Vec::new();
+        print_grouped_by_due(&tasks, None, Locale::English, now, &mut out).unwrap();
+        let output = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = output
+            .lines()
+            .map(|line| line.split(". Priority").next().unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "Overdue:",
+                "Task 3: \"Late\" Todo",
+                "Task 5: \"Also late\" Todo",
+                "This week:",
+                "Task 4: \"Friday\" Todo",
+                "Later:",
+                "Task 1: \"Later\" Todo",
+                "No due date:",
+                "Task 2: \"None\" Todo",
+            ]
+        );
+    }
+
+    #[test]
+    fn list_group_by_options() {
+        let parse = |extra_args: &[&str]| {
+            let args: Vec<String> = ["tasklist", "list"]
+                .iter()
+                .chain(extra_args)
+                .map(|arg| arg.to_string())
+                .collect();
+            parse_list_options(&args)
+        };
+        assert!(parse(&["--group-by", "due"]).unwrap().group_by_due);
+        assert_eq!(
+            parse(&["--group-by", "tag"]),
+            Err(TaskError::Invalid("Unknown grouping: tag".to_string()))
+        );
+        assert_eq!(
+            parse(&["--group-by", "due", "--json"]),
+            Err(TaskError::Invalid(
+                "Cannot give --group-by with --json or --ids".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn list_rejects_json_with_ids() {
+        let args: Vec<String> = ["tasklist", "list", "--json", "--ids"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            parse_list_options(&args),
+            Err(TaskError::Invalid(
+                "Cannot give both --json and --ids".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn list_json_utc_prints_times_in_utc() {
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        let created_at = FixedOffset::east_opt(2 * 3600)
+            .unwrap()
+            .with_ymd_and_hms(2024, 5, 1, 10, 30, 0)
+            .unwrap()
+            .with_timezone(&Local);
+        let task = repo.task(1).unwrap();
+        task.created_at = created_at;
+        task.updated_at = created_at;
+        task.due_date = Some(created_at + TimeDelta::days(1));
+        let args: Vec<String> = ["tasklist", "list", "--json", "--utc"]
+            .map(String::from)
+            .to_vec();
+        let mut out = Vec::new();
+        print_listed_tasks(
+            &repo,
+            &parse_list_options(&args).unwrap(),
+            Locale::default(),
+            &mut out,
+        )
+        .unwrap();
+        let utc: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(utc[0]["created_at"], "2024-05-01T08:30:00Z");
+        assert_eq!(utc[0]["updated_at"], "2024-05-01T08:30:00Z");
+        assert_eq!(utc[0]["due_date"], "2024-05-02T08:30:00Z");
+        assert_eq!(utc[0]["completed_at"], serde_json::Value::Null);
+
+        let local = serde_json::to_value(repo.task_ref(1).unwrap()).unwrap();
+        assert_eq!(
+            utc[0].as_object().unwrap().keys().collect::<Vec<_>>(),
+            local.as_object().unwrap().keys().collect::<Vec<_>>()
+        );
+        let loaded: Vec<Task> = serde_json::from_slice(&out).unwrap();
+        assert_eq!(loaded[0].created_at, created_at);
+    }
+
+    #[test]
+    fn cached_json_is_reused_until_the_task_file_changes() {
+        let (tmp_dir, ctx) = setup();
+        let cache_file = tmp_dir.path().join("list.cache");
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        save_repository(&mut repo, &ctx).unwrap();
+        let load = || task_repository::load_repository(&ctx.task_file);
+
+        let source_meta = fs::metadata(&ctx.task_file).unwrap();
+        let json = cached_json(load, &source_meta, &cache_file).unwrap();
+        let tasks: Vec<Task> = serde_json::from_str(&json).unwrap();
+        assert_eq!(tasks.len(), 1);
+        let reused = cached_json(
+            || panic!("tasks loaded despite the cache"),
+            &source_meta,
+            &cache_file,
+        )
+        .unwrap();
+        assert_eq!(reused, json);
+
+        repo.new_task("Plip".to_string());
+        save_repository(&mut repo, &ctx).unwrap();
+        let source_meta = fs::metadata(&ctx.task_file).unwrap();
+        let json = cached_json(load, &source_meta, &cache_file).unwrap();
+        let tasks: Vec<Task> = serde_json::from_str(&json).unwrap();
+        assert_eq!(tasks.len(), 2);
+    }
+
+    #[test]
+    fn json_cache_output_of_run_matches_list_json() {
+        let tmp_dir = TempDir::new().unwrap();
+        let task_file = tmp_dir.path().join("tasks.json");
+        let cache_file = tmp_dir.path().join("list.cache");
+        run_on(&task_file, &["add", "Plop"]);
+        let (_, listed, _) = run_on(&task_file, &["list", "--json"]);
+        let cache_arg = cache_file.to_str().unwrap();
+        for _ in 0..2 {
+            let (code, out, _) = run_on(&task_file, &["--json-cache", cache_arg, "list", "--json"]);
+            assert_eq!(code, ExitCode::SUCCESS);
+            assert_eq!(out, listed);
+        }
+        assert!(cache_file.exists());
+    }
+
+    #[test]
+    fn list_rejects_utc_without_json() {
+        let args: Vec<String> = ["tasklist", "list", "--utc"].map(String::from).to_vec();
+        assert_eq!(
+            parse_list_options(&args),
+            Err(TaskError::Invalid(
+                "Cannot give --utc without --json".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn list_compact_empty_prints_nothing() {
+        let mut repo = TaskRepository::default();
+        for extra_args in [vec!["--compact-empty"], vec!["done", "--compact-empty"]] {
+            let mut args: Vec<String> = ["tasklist", "list"].map(String::from).to_vec();
+            args.extend(extra_args.into_iter().map(String::from));
+            let mut out = Vec::new();
+            handle_list_command(&args, &repo, Locale::English, &mut out).unwrap();
+            assert!(out.is_empty());
+            repo.new_task("Plop".to_string());
+        }
+
+        let args: Vec<String> = ["tasklist", "list", "todo", "--compact-empty"]
+            .map(String::from)
+            .to_vec();
+        let mut out = Vec::new();
+        handle_list_command(&args, &repo, Locale::English, &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("Plop"));
+    }
+
+    #[test]
+    fn list_json_composes_with_filters() {
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        repo.new_task("Plip".to_string());
+        repo.new_task("Plap".to_string());
+        repo.set_status(2, TaskStatus::Done).unwrap();
+        repo.add_tag(3, "work".to_string()).unwrap();
+        let args: Vec<String> = ["tasklist", "list", "todo", "--json"]
+            .map(String::from)
+            .to_vec();
+        let mut out = Vec::new();
+        handle_list_command(&args, &repo, Locale::English, &mut out).unwrap();
+
+        let tasks: Vec<Task> = serde_json::from_slice(&out).unwrap();
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(&tasks[0], repo.task(1).unwrap());
+        assert_eq!(&tasks[1], repo.task(3).unwrap());
+        assert_eq!(tasks[1].tags, vec!["work"]);
+
+        let args: Vec<String> = ["tasklist", "list", "in-progress", "--json"]
+            .map(String::from)
+            .to_vec();
+        let mut out = Vec::new();
+        handle_list_command(&args, &repo, Locale::English, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "[]\n");
+    }
+
+    #[test]
+    fn list_filtered_by_tag() {
+        let mut repo = TaskRepository::default();
+        repo.new_task("Report".to_string());
+        repo.new_task("Groceries".to_string());
+        repo.new_task("Review".to_string());
+        repo.add_tag(1, "work".to_string()).unwrap();
+        repo.add_tag(3, "work".to_string()).unwrap();
+        repo.set_status(3, TaskStatus::Done).unwrap();
+
+        assert_eq!(listed_ids(&repo, &["--tag", "work"]), vec![1, 3]);
+        assert_eq!(listed_ids(&repo, &["--tag", "work", "todo"]), vec![1]);
+
+        let args: Vec<String> = ["tasklist", "list", "--tag", "home"]
+            .map(String::from)
+            .to_vec();
+        let mut out = Vec::new();
+        handle_list_command(&args, &repo, Locale::English, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "No task with tag home\n");
+    }
+
+    #[test]
+    fn task_added_from_file() {
+        let (tmp_dir, ctx) = setup();
+        let description_file = tmp_dir.path().join("description.txt");
+        fs::write(
+            &description_file,
+            "Write the report\nwith all the figures\n",
+        )
+        .unwrap();
+        let mut repo = TaskRepository::default();
+        let args: Vec<String> = [
+            "tasklist",
+            "add",
+            "--from-file",
+            description_file.to_str().unwrap(),
+        ]
+        .map(String::from)
+        .to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Ok(())
+        );
+        assert_eq!(
+            repo.task(1).unwrap().description,
+            "Write the report\nwith all the figures"
+        );
+    }
+
+    #[test]
+    fn task_added_from_missing_or_empty_file() {
+        let (tmp_dir, ctx) = setup();
+        let empty_file = tmp_dir.path().join("empty.txt");
+        fs::write(&empty_file, "\n").unwrap();
+        let missing_file = tmp_dir.path().join("missing.txt");
+        let mut repo = TaskRepository::default();
+        for path in [empty_file, missing_file] {
+            let args: Vec<String> = ["tasklist", "add", "--from-file", path.to_str().unwrap()]
+                .map(String::from)
+                .to_vec();
+            assert!(handle_command(&args, &mut repo, &ctx, &mut io::sink()).is_err());
+        }
+        assert_eq!(repo.task_count(), 0);
+    }
+
+    #[test]
+    fn extract_hashtags_splits_tags_out() {
+        assert_eq!(
+            extract_hashtags("pay rent #home  #urgent"),
+            (
+                "pay rent".to_string(),
+                vec!["home".to_string(), "urgent".to_string()]
+            )
+        );
+        assert_eq!(
+            extract_hashtags("call #bob about it # later"),
+            ("call about it # later".to_string(), vec!["bob".to_string()])
+        );
+        assert_eq!(
+            extract_hashtags("two\nlines #tag"),
+            ("two\nlines".to_string(), vec!["tag".to_string()])
+        );
+        assert_eq!(
+            extract_hashtags("fix #42 before #work"),
+            ("fix #42 before".to_string(), vec!["work".to_string()])
+        );
+    }
+
+    #[test]
+    fn extract_hashtags_keeps_the_rest_of_the_text() {
+        let desc = "Steps:\n    1. fix  #42   first\n    #todo  2. ship  it #now\n\n  done  ";
+        assert_eq!(
+            extract_hashtags(desc),
+            (
+                "Steps:\n    1. fix  #42   first\n    2. ship  it\n\n  done  ".to_string(),
+                vec!["todo".to_string(), "now".to_string()]
+            )
+        );
+        let plain = "  indented\n\ttabbed   spaced\r\n";
+        assert_eq!(extract_hashtags(plain), (plain.to_string(), Vec::new()));
+    }
+
+    #[test]
+    fn task_added_from_file_keeps_indentation() {
+        let (tmp_dir, ctx) = setup();
+        let description_file = tmp_dir.path().join("description.txt");
+        fs::write(
+            &description_file,
+            "Release:\n    - tag  it\n    - ship #42\n",
+        )
+        .unwrap();
+        let mut repo = TaskRepository::default();
+        let args: Vec<String> = [
+            "tasklist",
+            "add",
+            "--from-file",
+            description_file.to_str().unwrap(),
+        ]
+        .map(String::from)
+        .to_vec();
+        handle_command(&args, &mut repo, &ctx, &mut io::sink()).unwrap();
+        let task = repo.task(1).unwrap();
+        assert_eq!(task.description, "Release:\n    - tag  it\n    - ship #42");
+        assert!(task.tags.is_empty());
+    }
+
+    #[test]
+    fn add_promotes_hashtags_to_tags() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        let args: Vec<String> = ["tasklist", "add", "pay rent #home #urgent", "--tag", "home"]
+            .map(String::from)
+            .to_vec();
+        handle_command(&args, &mut repo, &ctx, &mut io::sink()).unwrap();
+        let task = repo.task(1).unwrap();
+        assert_eq!(task.description, "pay rent");
+        assert_eq!(task.tags, vec!["home", "urgent"]);
+
+        let args: Vec<String> = ["tasklist", "add", "pay rent #home", "--keep-hashtags"]
+            .map(String::from)
+            .to_vec();
+        handle_command(&args, &mut repo, &ctx, &mut io::sink()).unwrap();
+        let task = repo.task(2).unwrap();
+        assert_eq!(task.description, "pay rent #home");
+        assert_eq!(task.tags, vec!["home"]);
+
+        let args: Vec<String> = ["tasklist", "add", "#home"].map(String::from).to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Err(TaskError::Invalid(
+                "Description cannot be empty".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn blank_descriptions_are_rejected() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        for description in ["", "  \t "] {
+            for args in [
+                vec!["tasklist", "add", description],
+                vec!["tasklist", "update", "1", description],
+            ] {
+                let args: Vec<String> = args.into_iter().map(String::from).collect();
+                assert_eq!(
+                    handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+                    Err(TaskError::Invalid(
+                        "Description cannot be empty".to_string()
+                    ))
+                );
+            }
+        }
+        assert_eq!(repo.task_count(), 1);
+        assert_eq!(repo.task(1).unwrap().description, "Plop");
+    }
+
+    #[test]
+    fn descriptions_are_stored_trimmed() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        let args: Vec<String> = ["tasklist", "add", "  Plop  "].map(String::from).to_vec();
+        handle_command(&args, &mut repo, &ctx, &mut io::sink()).unwrap();
+        assert_eq!(repo.task(1).unwrap().description, "Plop");
+
+        let args: Vec<String> = ["tasklist", "update", "1", " Plip\n"]
+            .map(String::from)
+            .to_vec();
+        handle_command(&args, &mut repo, &ctx, &mut io::sink()).unwrap();
+        assert_eq!(repo.task(1).unwrap().description, "Plip");
+    }
+
+    #[test]
+    fn repeated_options_are_rejected() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        for (args, option) in [
+            (vec!["list", "--sort", "id", "--sort", "updated"], "--sort"),
+            (
+                vec!["list", "--since-id", "1", "--since-id", "2"],
+                "--since-id",
+            ),
+            (vec!["add", "Plop", "--id", "3", "--id", "4"], "--id"),
+            (
+                vec![
+                    "add",
+                    "Plop",
+                    "--at",
+                    "2024-01-01T00:00:00",
+                    "--at",
+                    "2024-01-02T00:00:00",
+                ],
+                "--at",
+            ),
+            (
+                vec![
+                    "delete",
+                    "--older-than",
+                    "1d",
+                    "--older-than",
+                    "2d",
+                    "--yes",
+                ],
+                "--older-than",
+            ),
+        ] {
+            let mut full_args = vec!["tasklist".to_string()];
+            full_args.extend(args.into_iter().map(String::from));
+            assert_eq!(
+                handle_command(&full_args, &mut repo, &ctx, &mut io::sink()),
+                Err(TaskError::Invalid(format!(
+                    "Option {} given more than once",
+                    option
+                )))
+            );
+        }
+        let args: Vec<String> = ["tasklist", "-f", "a.json", "--file", "b.json", "list"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            parse_global_options(&args),
+            Err(TaskError::Invalid(
+                "Option --file given more than once".to_string()
+            ))
+        );
+        assert_eq!(repo.task_count(), 0);
+    }
+
+    #[test]
+    fn repeated_flags_are_harmless() {
+        let repo = repo_to_sort();
+        assert_eq!(
+            listed_ids(&repo, &["--reverse", "--reverse"]),
+            vec![3, 2, 1]
+        );
+        assert_eq!(listed_ids(&repo, &["todo", "todo"]), vec![2]);
+    }
+
+    #[test]
+    fn description_and_from_file_conflict() {
+        let (tmp_dir, ctx) = setup();
+        let description_file = tmp_dir.path().join("description.txt");
+        fs::write(&description_file, "From file").unwrap();
+        let path = description_file.to_str().unwrap();
+        let mut repo = TaskRepository::default();
+        let args: Vec<String> = ["tasklist", "add", "Plop", "--from-file", path]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Err(TaskError::Invalid(
+                "Cannot give both a description and --from-file".to_string()
+            ))
+        );
+        let args: Vec<String> = ["tasklist", "add", "--from-file", path, "high", "low"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Err(TaskError::Invalid(
+                "Cannot give both a description and --from-file".to_string()
+            ))
+        );
+        let args: Vec<String> = ["tasklist", "add", "--from-file", path, "high"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Ok(())
+        );
+        assert_eq!(repo.task(1).unwrap().description, "From file");
+        assert_eq!(repo.task(1).unwrap().priority, Priority::High);
+    }
+
+    #[test]
+    fn task_added_with_chosen_id() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        let args: Vec<String> = ["tasklist", "add", "--id", "7", "Plop"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Ok(())
+        );
+        assert_eq!(repo.task(7).unwrap().description, "Plop");
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Err(TaskError::Invalid(
+                "A task with id 7 already exists".to_string()
+            ))
+        );
+        assert_eq!(repo.task_count(), 1);
+    }
+
+    #[test]
+    fn parse_timestamp_accepts_local_and_offset_forms() {
+        assert_eq!(
+            parse_timestamp("2024-01-15T08:30:00Z").unwrap(),
+            parse_timestamp("2024-01-15T10:30:00+02:00").unwrap()
+        );
+        assert!(parse_timestamp("2024-01-15T08:30:00").is_ok());
+        assert_eq!(
+            parse_timestamp("yesterday"),
+            Err(TaskError::Invalid(
+                "Invalid timestamp: yesterday".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_due_date_accepts_dates_and_timestamps() {
+        assert_eq!(
+            parse_due_date("2024-05-01", Locale::English),
+            parse_timestamp("2024-05-01T23:59:59")
+        );
+        assert_eq!(
+            parse_due_date("2024-05-01T10:00:00+02:00", Locale::English),
+            parse_timestamp("2024-05-01T10:00:00+02:00")
+        );
+        assert_eq!(
+            parse_due_date("2024-13-01", Locale::English),
+            Err(TaskError::Invalid(
+                "Invalid due date: 2024-13-01".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_due_date_accepts_weekdays_of_the_locale() {
+        let next_monday = next_weekday(Weekday::Mon, Local::now().date_naive()).unwrap();
+        assert_eq!(
+            parse_due_date("lundi", Locale::French),
+            Ok(end_of_day(next_monday).unwrap())
+        );
+        assert_eq!(
+            parse_due_date("Monday", Locale::English),
+            Ok(end_of_day(next_monday).unwrap())
+        );
+        assert_eq!(
+            parse_due_date("lundi", Locale::English),
+            Err(TaskError::Invalid("Invalid due date: lundi".to_string()))
+        );
+    }
+
+    #[test]
+    fn next_weekday_is_today_or_later_in_the_week() {
+        // 2024-05-15 is a Wednesday.
+        let today = NaiveDate::from_ymd_opt(2024, 5, 15).unwrap();
+        let day = |d| NaiveDate::from_ymd_opt(2024, 5, d);
+        assert_eq!(next_weekday(Weekday::Wed, today), day(15));
+        assert_eq!(next_weekday(Weekday::Fri, today), day(17));
+        assert_eq!(next_weekday(Weekday::Mon, today), day(20));
+    }
+
+    #[test]
+    fn bump_makes_task_due_today() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        repo.set_due_date(
+            1,
+            Some(parse_due_date("2999-01-01", Locale::English).unwrap()),
+        )
+        .unwrap();
+        repo.set_status(1, TaskStatus::InProgress).unwrap();
+        let args: Vec<String> = ["tasklist", "bump", "1"].map(String::from).to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Ok(())
+        );
+
+        let task = repo.task(1).unwrap();
+        let due_date = task.due_date.unwrap();
+        assert_eq!(due_date.date_naive(), Local::now().date_naive());
+        assert_eq!(
+            due_date.time(),
+            NaiveTime::from_hms_opt(23, 59, 59).unwrap()
+        );
+        assert_eq!(task.status, TaskStatus::Todo);
+        assert_eq!(
+            task_repository::load_repository(&ctx.task_file).unwrap(),
+            repo
+        );
+    }
+
+    #[test]
+    fn bump_rejects_done_task() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        repo.set_status(1, TaskStatus::Done).unwrap();
+        let args: Vec<String> = ["tasklist", "bump", "1"].map(String::from).to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Err(TaskError::Invalid("Task 1 is done".to_string()))
+        );
+        assert!(!ctx.task_file.exists());
+    }
+
+    #[test]
+    fn list_overdue_tasks() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Late".to_string());
+        repo.new_task("Late but done".to_string());
+        repo.new_task("Far away".to_string());
+        for args in [
+            ["tasklist", "set-due", "1", "2020-01-01"],
+            ["tasklist", "set-due", "2", "2020-01-01"],
+            ["tasklist", "set-due", "3", "2999-01-01"],
+        ] {
+            let args: Vec<String> = args.map(String::from).to_vec();
+            assert_eq!(
+                handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+                Ok(())
+            );
+        }
+        repo.set_status(2, TaskStatus::Done).unwrap();
+
+        let args: Vec<String> = ["tasklist", "list", "overdue"].map(String::from).to_vec();
+        let mut out = Vec::new();
+        handle_list_command(&args, &repo, Locale::English, &mut out).unwrap();
+        let output = String::from_utf8(out).unwrap();
+        assert_eq!(output.lines().count(), 1);
+        assert!(output.contains("Late"));
+        assert!(output.contains(". Due: 2020-01-01 23:59:59"));
+
+        repo.set_status(1, TaskStatus::Done).unwrap();
+        let mut out = Vec::new();
+        handle_list_command(&args, &repo, Locale::English, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "No overdue task\n");
+    }
+
+    #[test]
+    fn list_options_combine_overdue_with_other_filters() {
+        let mut repo = TaskRepository::default();
+        for desc in ["Late", "Late, in progress", "Late, tagged", "Far away"] {
+            repo.new_task(desc.to_string());
+        }
+        let now = Local::now();
+        for id in 1..=3 {
+            repo.task(id).unwrap().due_date = Some(now - TimeDelta::days(1));
+        }
+        repo.task(4).unwrap().due_date = Some(now + TimeDelta::days(1));
+        repo.set_status(2, TaskStatus::InProgress).unwrap();
+        repo.add_tag(3, "home".to_string()).unwrap();
+        repo.add_tag(4, "home".to_string()).unwrap();
+
+        for (extra_args, expected) in [
+            (vec!["overdue"], vec![1, 2, 3]),
+            (vec!["overdue", "in-progress"], vec![2]),
+            (vec!["overdue", "--tag", "home"], vec![3]),
+            (vec!["overdue", "--since-id", "1"], vec![2, 3]),
+        ] {
+            let mut args: Vec<String> = ["tasklist", "list"].map(String::from).to_vec();
+            args.extend(extra_args.iter().map(|arg| arg.to_string()));
+            let options = parse_list_options(&args).unwrap();
+            let ids: Vec<i32> = repo
+                .tasks()
+                .filter(|task| options.matches(task, now))
+                .map(|task| task.id)
+                .collect();
+            assert_eq!(ids, expected, "{:?}", extra_args);
+        }
+    }
+
+    #[test]
+    fn task_id_incremental() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("TestTask"));
+        repo.new_task(String::from("otherTask"));
+        let task2 = repo.task(2).unwrap();
+        assert_eq!(task2.description, "otherTask");
+        assert_eq!(task2.id, 2);
+    }
+
+    #[test]
+    fn list_task() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("TestTask"));
+        repo.new_task(String::from("otherTask"));
+        assert_eq!(repo.task_count(), 2);
+    }
+
+    #[test]
+    fn delete_task() {
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        repo.new_task("Plip".to_string());
+        repo.delete(1);
+        assert_eq!(repo.task_count(), 1);
+        let task = repo.task(2).unwrap();
+        assert_eq!(task.id, 2);
+        assert_eq!(task.description, String::from("Plip"));
+        assert_eq!(task.status, TaskStatus::Todo);
+    }
+
+    #[test]
+    fn delete_range_of_tasks() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        for desc in ["Plop", "Plip", "Plap", "Plup"] {
+            repo.new_task(desc.to_string());
+        }
+        let list = parse_id_list(&repo, &["2-5".to_string(), "6".to_string()]).unwrap();
+        assert_eq!(list.missing, ["5", "6"]);
+        let missing = delete_tasks(&mut repo, &list.ids, &ctx).unwrap();
+        assert!(missing.is_empty());
+        assert_eq!(repo.task_count(), 1);
+        assert_eq!(repo.task(1).unwrap().description, "Plop");
+    }
+
+    #[test]
+    fn delete_partly_missing_range_reports_missing_sub_ranges() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        for desc in ["Plop", "Plip", "Plap", "Plup", "Plep"] {
+            repo.new_task(desc.to_string());
+        }
+        repo.delete(3);
+        let args: Vec<String> = ["tasklist", "delete", "2-9", "-y"]
+            .map(String::from)
+            .to_vec();
+        let mut out = Vec::new();
+        assert_eq!(handle_command(&args, &mut repo, &ctx, &mut out), Ok(()));
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("No task with id 3, 6-9\n"));
+        assert_eq!(repo.tasks().map(|task| task.id).collect::<Vec<_>>(), [1]);
+    }
+
+    #[test]
+    fn delete_fully_missing_range_is_an_error_and_saves_nothing() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        let args: Vec<String> = ["tasklist", "delete", "5-7", "--yes"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Err(TaskError::Invalid("No task with id 5-7".to_string()))
+        );
+        assert_eq!(repo.task_count(), 1);
+        assert!(!ctx.task_file.exists());
+    }
+
+    #[test]
+    fn delete_several_tasks() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        for desc in ["Plop", "Plip", "Plap", "Plup", "Plep"] {
+            repo.new_task(desc.to_string());
+        }
+        let args: Vec<String> = ["tasklist", "delete", "1", "3", "5", "--yes"]
+            .map(String::from)
+            .to_vec();
+        let mut out = Vec::new();
+        assert_eq!(handle_command(&args, &mut repo, &ctx, &mut out), Ok(()));
+        assert_eq!(ctx.saves.get(), 1);
+        let output = String::from_utf8(out).unwrap();
+        assert!(!output.contains("No task with id"));
+        let ids: Vec<i32> = repo.tasks().map(|task| task.id).collect();
+        assert_eq!(ids, vec![2, 4]);
+        assert_eq!(
+            task_repository::load_repository(&ctx.task_file)
+                .unwrap()
+                .task_count(),
+            2
+        );
+    }
+
+    #[test]
+    fn delete_confirmed_on_input() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        for desc in ["Plop", "Plip", "Plap"] {
+            repo.new_task(desc.to_string());
+        }
+        let args: Vec<String> = ["tasklist", "delete", "1-3"].map(String::from).to_vec();
+        let mut input = "y\nno\nYES\n".as_bytes();
+        let mut out = Vec::new();
+        assert_eq!(
+            handle_delete_command(&args, &mut repo, &ctx, &mut input, &mut out),
+            Ok(())
+        );
+        let output = String::from_utf8(out).unwrap();
+        assert!(output.starts_with(
+            "Delete task 1 (\"Plop\")? [y/N] Delete task 2 (\"Plip\")? [y/N] Delete task 3 (\"Plap\")? [y/N] "
+        ));
+        let ids: Vec<i32> = repo.tasks().map(|task| task.id).collect();
+        assert_eq!(ids, vec![2]);
+        let loaded_repo = task_repository::load_repository(&ctx.task_file).unwrap();
+        let loaded_ids: Vec<i32> = loaded_repo.tasks().map(|task| task.id).collect();
+        assert_eq!(loaded_ids, ids);
+    }
+
+    #[test]
+    fn delete_declined_keeps_task() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        let args: Vec<String> = ["tasklist", "delete", "1"].map(String::from).to_vec();
+        for answer in ["n\n", "\n", "maybe\n", ""] {
+            let mut out = Vec::new();
+            assert_eq!(
+                handle_delete_command(&args, &mut repo, &ctx, &mut answer.as_bytes(), &mut out),
+                Ok(())
+            );
+            assert!(String::from_utf8(out)
+                .unwrap()
+                .ends_with("[y/N] Nothing deleted\n"));
+            assert_eq!(repo.task_count(), 1);
+        }
+        assert!(!ctx.task_file.exists());
+    }
+
+    #[test]
+    fn delete_with_yes_does_not_prompt() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        let args: Vec<String> = ["tasklist", "delete", "1", "--yes"]
+            .map(String::from)
+            .to_vec();
+        let mut input = "n\n".as_bytes();
+        let mut out = Vec::new();
+        assert_eq!(
+            handle_delete_command(&args, &mut repo, &ctx, &mut input, &mut out),
+            Ok(())
+        );
+        assert!(!String::from_utf8(out).unwrap().contains("[y/N]"));
+        assert_eq!(repo.task_count(), 0);
+        assert_eq!(input, "n\n".as_bytes());
+    }
+
+    #[test]
+    fn delete_reports_missing_ids() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        repo.new_task("Plip".to_string());
+        let args: Vec<String> = ["tasklist", "delete", "7", "2", "9", "-y"]
+            .map(String::from)
+            .to_vec();
+        let mut out = Vec::new();
+        assert_eq!(handle_command(&args, &mut repo, &ctx, &mut out), Ok(()));
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("No task with id 7, 9\n"));
+        assert_eq!(repo.task_count(), 1);
+        assert!(repo.task(2).is_none());
+    }
+
+    #[test]
+    fn delete_older_than_removes_only_old_tasks() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        let now = Local::now();
+        repo.new_task_at("Recent".to_string(), now - TimeDelta::days(10));
+        repo.new_task_at("Old".to_string(), now - TimeDelta::days(100));
+        repo.new_task_at("Older".to_string(), now - TimeDelta::days(365));
+        repo.task(3).unwrap().status = TaskStatus::Done;
+        repo.task(2).unwrap().status = TaskStatus::InProgress;
+
+        let removed = delete_tasks_older_than(&mut repo, TimeDelta::days(90), now, &ctx).unwrap();
+        assert_eq!(removed, 2);
+        assert_eq!(repo.task_count(), 1);
+        assert_eq!(repo.task(1).unwrap().description, "Recent");
+    }
+
+    #[test]
+    fn delete_dry_run_prints_matched_count() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        let now = Local::now();
+        repo.new_task_at("Recent".to_string(), now - TimeDelta::days(10));
+        repo.new_task_at("Old".to_string(), now - TimeDelta::days(100));
+        repo.new_task_at("Older".to_string(), now - TimeDelta::days(365));
+        for (args, matched) in [
+            (vec!["--older-than", "90d", "--dry-run"], 2),
+            (vec!["1-2", "7", "--dry-run"], 2),
+        ] {
+            let mut full_args: Vec<String> = ["tasklist", "delete"].map(String::from).to_vec();
+            full_args.extend(args.into_iter().map(String::from));
+            let mut out = Vec::new();
+            assert_eq!(
+                handle_command(&full_args, &mut repo, &ctx, &mut out),
+                Ok(())
+            );
+            let output = String::from_utf8(out).unwrap();
+            assert_eq!(
+                output.lines().next(),
+                Some(format!("Matched {} task(s)", matched).as_str())
+            );
+            assert_eq!(output.lines().count(), matched + 1);
+        }
+        assert_eq!(repo.task_count(), 3);
+        assert!(!ctx.task_file.exists());
+    }
+
+    #[test]
+    fn delete_older_than_confirms_above_threshold() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        for i in 0..=DEFAULT_CONFIRM_THRESHOLD {
+            repo.new_task_at(format!("Old {}", i), Local::now() - TimeDelta::days(100));
+        }
+        let args: Vec<String> = ["tasklist", "delete", "--older-than", "90d"]
+            .map(String::from)
+            .to_vec();
+        let mut out = Vec::new();
+        let mut input = "n\n".as_bytes();
+        assert_eq!(
+            handle_delete_command(&args, &mut repo, &ctx, &mut input, &mut out),
+            Ok(())
+        );
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Delete 6 task(s)? [y/N] Nothing deleted\n"
+        );
+        assert_eq!(repo.task_count(), 6);
+        assert!(!ctx.task_file.exists());
+
+        let mut input = "y\n".as_bytes();
+        assert_eq!(
+            handle_delete_command(&args, &mut repo, &ctx, &mut input, &mut io::sink()),
+            Ok(())
+        );
+        assert_eq!(repo.task_count(), 0);
+    }
+
+    #[test]
+    fn delete_older_than_below_threshold_needs_no_confirmation() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task_at("Old".to_string(), Local::now() - TimeDelta::days(100));
+        repo.new_task("New".to_string());
+        let args: Vec<String> = ["tasklist", "delete", "--older-than", "90d"]
+            .map(String::from)
+            .to_vec();
+        let mut out = Vec::new();
+        assert_eq!(
+            handle_delete_command(&args, &mut repo, &ctx, &mut io::empty(), &mut out),
+            Ok(())
+        );
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains("Removed 1 task(s)"));
+        assert_eq!(repo.task_count(), 1);
+    }
+
+    #[test]
+    fn parse_id_list_mixes_ranges_and_single_ids() {
+        let mut repo = TaskRepository::default();
+        for i in 1..=10 {
+            repo.new_task(format!("Task {}", i));
+        }
+        let args: Vec<String> = ["1-3", "7", "9-10"].map(String::from).to_vec();
+        assert_eq!(
+            parse_id_list(&repo, &args),
+            Ok(IdList {
+                ids: vec![1, 2, 3, 7, 9, 10],
+                missing: Vec::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_id_list_expands_wide_range_to_existing_ids_only() {
+        let mut repo = TaskRepository::default();
+        for desc in ["Plop", "Plip", "Plap"] {
+            repo.new_task(desc.to_string());
+        }
+        repo.delete(2);
+        let args: Vec<String> = ["1-2000000000", "12", "2147483646-2147483647"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            parse_id_list(&repo, &args),
+            Ok(IdList {
+                ids: vec![1, 3],
+                missing: ["2", "4-2000000000", "12", "2147483646-2147483647"]
+                    .map(String::from)
+                    .to_vec(),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_id_list_rejects_malformed_range() {
+        assert_eq!(
+            parse_id_list(&TaskRepository::default(), &["1-x".to_string()]),
+            Err(TaskError::Invalid("Invalid id range: 1-x".to_string()))
+        );
+        assert_eq!(
+            parse_id_list(&TaskRepository::default(), &["5-1".to_string()]),
+            Err(TaskError::Invalid("Invalid id range: 5-1".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_list_since_id() {
+        let args: Vec<String> = ["tasklist", "list", "--since-id", "3", "todo"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            parse_list_options(&args),
+            Ok(ListOptions {
+                statuses: vec![TaskStatus::Todo],
+                since_id: Some(3),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn parse_list_several_statuses() {
+        let args: Vec<String> = ["tasklist", "list", "todo", "in-progress", "todo"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            parse_list_options(&args),
+            Ok(ListOptions {
+                statuses: vec![TaskStatus::Todo, TaskStatus::InProgress],
+                ..Default::default()
+            })
+        );
+        let args: Vec<String> = ["tasklist", "list", "todo", "plop"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            parse_list_options(&args),
+            Err(TaskError::Invalid("Unknown status to display".to_string()))
+        );
+    }
+
+    #[test]
+    fn list_several_statuses_excludes_others() {
+        let mut repo = TaskRepository::default();
+        repo.new_task("Todo task".to_string());
+        repo.new_task("Ongoing task".to_string());
+        repo.new_task("Finished task".to_string());
+        repo.set_status(2, TaskStatus::InProgress).unwrap();
+        repo.set_status(3, TaskStatus::Done).unwrap();
+        let args: Vec<String> = ["tasklist", "list", "todo", "in-progress"]
+            .map(String::from)
+            .to_vec();
+        let mut out = Vec::new();
+        handle_list_command(&args, &repo, Locale::English, &mut out).unwrap();
+        let output = String::from_utf8(out).unwrap();
+        assert!(output.contains("Todo task"));
+        assert!(output.contains("Ongoing task"));
+        assert!(!output.contains("Finished task"));
+    }
+
+    #[test]
+    fn list_status_option_takes_comma_separated_statuses() {
+        let mut repo = TaskRepository::default();
+        repo.new_task("Todo task".to_string());
+        repo.new_task("Ongoing task".to_string());
+        repo.new_task("Finished task".to_string());
+        repo.set_status(2, TaskStatus::InProgress).unwrap();
+        repo.set_status(3, TaskStatus::Done).unwrap();
+        let args: Vec<String> = ["tasklist", "list", "--status", "todo,in-progress", "--json"]
+            .map(String::from)
+            .to_vec();
+        let mut out = Vec::new();
+        handle_list_command(&args, &repo, Locale::English, &mut out).unwrap();
+
+        let tasks: Vec<Task> = serde_json::from_slice(&out).unwrap();
+        let ids: Vec<i32> = tasks.iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+    }
+
+    #[test]
+    fn list_status_option_rejects_unknown_status() {
+        let repo = TaskRepository::default();
+        let args: Vec<String> = ["tasklist", "list", "--status", "todo,doing"]
+            .map(String::from)
+            .to_vec();
+        let mut out = Vec::new();
+        assert_eq!(
+            handle_list_command(&args, &repo, Locale::English, &mut out),
+            Err(TaskError::Invalid("Unknown status: doing".to_string()))
+        );
+    }
+
+    /// Lists the repository with the given extra arguments and returns the ids in output order.
+    fn listed_ids(repo: &TaskRepository, extra_args: &[&str]) -> Vec<i32> {
+        let mut args: Vec<String> = ["tasklist", "list"].map(String::from).to_vec();
+        args.extend(extra_args.iter().map(|arg| arg.to_string()));
+        let mut out = Vec::new();
+        handle_list_command(&args, repo, Locale::English, &mut out).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let id = line.trim_start_matches("Task ").split(':').next().unwrap();
+                id.parse().unwrap()
+            })
+            .collect()
+    }
+
+    /// A repository whose creation, update and status orders all differ from the id order.
+    fn repo_to_sort() -> TaskRepository {
+        let now = Local::now();
+        let mut repo = TaskRepository::default();
+        repo.new_task_at("First".to_string(), now - TimeDelta::days(2));
+        repo.new_task_at("Second".to_string(), now - TimeDelta::days(3));
+        repo.new_task_at("Third".to_string(), now - TimeDelta::days(1));
+        repo.set_status(1, TaskStatus::Done).unwrap();
+        repo.set_status(3, TaskStatus::InProgress).unwrap();
+        repo.task(1).unwrap().updated_at = now - TimeDelta::hours(1);
+        repo.task(2).unwrap().updated_at = now - TimeDelta::hours(2);
+        repo.task(3).unwrap().updated_at = now - TimeDelta::hours(3);
+        repo
+    }
+
+    /// Lists the repository with the given extra arguments and returns the output.
+    fn list_output(repo: &TaskRepository, extra_args: &[&str]) -> String {
+        let mut args: Vec<String> = ["tasklist", "list"].map(String::from).to_vec();
+        args.extend(extra_args.iter().map(|arg| arg.to_string()));
+        let mut out = Vec::new();
+        handle_list_command(&args, repo, Locale::English, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn list_with_limit_shows_first_page() {
+        let mut repo = TaskRepository::default();
+        for desc in ["Plop", "Plip", "Plap", "Plup"] {
+            repo.new_task(desc.to_string());
+        }
+        let output = list_output(&repo, &["--limit", "2"]);
+        assert!(output.contains("Plop") && output.contains("Plip"));
+        assert!(!output.contains("Plap") && !output.contains("Plup"));
+        assert!(output.ends_with("Showing 0-1 of 4\n"));
+        assert_eq!(list_output(&repo, &["--limit", "2", "--ids"]), "1\n2\n");
+        assert_eq!(
+            list_output(
+                &repo,
+                &["--limit", "2", "--offset", "1", "--ids", "--reverse"]
+            ),
+            "3\n2\n"
+        );
+    }
+
+    #[test]
+    fn list_with_offset_beyond_end_is_empty() {
+        let mut repo = TaskRepository::default();
+        for desc in ["Plop", "Plip"] {
+            repo.new_task(desc.to_string());
+        }
+        assert_eq!(
+            list_output(&repo, &["--offset", "5"]),
+            "No task at offset 2 of 2\n"
+        );
+        assert_eq!(list_output(&repo, &["--offset", "2", "--json"]), "[]\n");
+        assert_eq!(
+            list_output(&repo, &["--offset", "2", "--compact-empty"]),
+            ""
+        );
+    }
+
+    #[test]
+    fn list_page_past_the_end_is_cut_short() {
+        let mut repo = TaskRepository::default();
+        for desc in ["Plop", "Plip", "Plap"] {
+            repo.new_task(desc.to_string());
+        }
+        repo.set_status(1, TaskStatus::Done).unwrap();
+        let output = list_output(&repo, &["todo", "--limit", "2", "--offset", "1"]);
+        assert!(output.contains("Plap"));
+        assert!(!output.contains("Plop") && !output.contains("Plip"));
+        assert!(output.ends_with("Showing 1-1 of 2\n"));
+
+        let args: Vec<String> = ["tasklist", "list", "--limit", "0"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            handle_list_command(&args, &repo, Locale::English, &mut io::sink()),
+            Err(TaskError::Invalid("Invalid limit: 0".to_string()))
+        );
+    }
+
+    #[test]
+    fn list_sorted_by_id_by_default() {
+        let repo = repo_to_sort();
+        assert_eq!(listed_ids(&repo, &[]), vec![1, 2, 3]);
+        assert_eq!(listed_ids(&repo, &["--sort", "id"]), vec![1, 2, 3]);
+        assert_eq!(listed_ids(&repo, &["--reverse"]), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn list_sorted_by_field() {
+        let repo = repo_to_sort();
+        assert_eq!(listed_ids(&repo, &["--sort", "created"]), vec![2, 1, 3]);
+        assert_eq!(listed_ids(&repo, &["--sort", "updated"]), vec![3, 2, 1]);
+        assert_eq!(listed_ids(&repo, &["--sort", "status"]), vec![2, 3, 1]);
+        assert_eq!(
+            listed_ids(&repo, &["--sort", "status", "--reverse"]),
+            vec![1, 3, 2]
+        );
+        assert_eq!(
+            listed_ids(&repo, &["todo", "done", "--sort", "created"]),
+            vec![2, 1]
+        );
+    }
+
+    #[test]
+    fn list_unknown_sort_field() {
+        let args: Vec<String> = ["tasklist", "list", "--sort", "plop"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            parse_list_options(&args),
+            Err(TaskError::Invalid("Unknown sort field: plop".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_list_since_id_requires_value() {
+        let args: Vec<String> = ["tasklist", "list", "--since-id"]
+            .map(String::from)
+            .to_vec();
+        assert!(parse_list_options(&args).is_err());
+    }
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("45m"), Ok(TimeDelta::minutes(45)));
+        assert_eq!(parse_duration("12h"), Ok(TimeDelta::hours(12)));
+        assert_eq!(parse_duration("30d"), Ok(TimeDelta::days(30)));
+        assert_eq!(parse_duration("2w"), Ok(TimeDelta::weeks(2)));
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("-3d").is_err());
+        assert!(parse_duration("").is_err());
+    }
+
+    #[test]
+    fn search_matches_description_ignoring_case() {
+        let mut repo = TaskRepository::default();
+        repo.new_task("Buy milk".to_string());
+        repo.new_task("Call the bank".to_string());
+        let args: Vec<String> = ["tasklist", "search", "MILK"].map(String::from).to_vec();
+        let mut out = Vec::new();
+        handle_search_command(&args, &repo, &mut out).unwrap();
+        let output = String::from_utf8(out).unwrap();
+        assert!(output.contains("Buy milk"));
+        assert!(!output.contains("Call the bank"));
+    }
+
+    #[test]
+    fn stats_counts_tasks_per_status() {
+        let mut repo = TaskRepository::default();
+        for desc in ["Plop", "Plip", "Plap", "Plup", "Plep"] {
+            repo.new_task(desc.to_string());
+        }
+        repo.set_status(1, TaskStatus::Done).unwrap();
+        repo.set_status(2, TaskStatus::InProgress).unwrap();
+        repo.set_status(3, TaskStatus::InProgress).unwrap();
+        let mut out = Vec::new();
+        handle_stats_command(
+            &["tasklist".to_string(), "stats".to_string()],
+            &repo,
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Total: 5\nTodo: 2\nIn Progress: 2\nDone: 1\n"
+        );
+    }
+
+    #[test]
+    fn stats_json_prints_completion_rate() {
+        let mut repo = TaskRepository::default();
+        for desc in ["Plop", "Plip", "Plap", "Plup"] {
+            repo.new_task(desc.to_string());
+        }
+        repo.set_status(1, TaskStatus::Done).unwrap();
+        repo.set_status(2, TaskStatus::InProgress).unwrap();
+        assert_eq!(
+            CompletionStats::of(&repo),
+            CompletionStats {
+                total: 4,
+                done: 1,
+                completion_rate: 0.25,
+            }
+        );
+        let args: Vec<String> = ["tasklist", "stats", "--json"].map(String::from).to_vec();
+        let mut out = Vec::new();
+        handle_stats_command(&args, &repo, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"total\":4,\"done\":1,\"completion_rate\":0.25}\n"
+        );
+    }
+
+    #[test]
+    fn stats_json_of_empty_repo_has_zero_rate() {
+        let args: Vec<String> = ["tasklist", "stats", "--json"].map(String::from).to_vec();
+        let mut out = Vec::new();
+        handle_stats_command(&args, &TaskRepository::default(), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"total\":0,\"done\":0,\"completion_rate\":0.0}\n"
+        );
+    }
+
+    #[test]
+    fn show_prints_every_field() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        let created_at = parse_timestamp("2024-01-15T08:30:00").unwrap();
+        let due_date = parse_due_date("2024-02-01", Locale::English).unwrap();
+        repo.new_task_at("Plop".to_string(), created_at);
+        repo.new_task_at("Plip".to_string(), created_at);
+        let task = repo.task(1).unwrap();
+        task.due_date = Some(due_date);
+        task.tags = vec![Tag::from("work"), Tag::from("home")];
+        let args: Vec<String> = ["tasklist", "show", "1"].map(String::from).to_vec();
+        let mut out = Vec::new();
+        assert_eq!(handle_command(&args, &mut repo, &ctx, &mut out), Ok(()));
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "Id: 1\nDescription: Plop\nStatus: Todo\nPriority: Medium\nCreated at: {}\n\
+                 Last update: {} ({})\nDue: {}\nCompleted at: none\nTags: work, home\n\
+                 Checklist: none\n",
+                created_at,
+                created_at,
+                Locale::English.humanize(Local::now() - created_at),
+                due_date
+            )
+        );
+        assert!(!ctx.task_file.exists());
+    }
+
+    #[test]
+    fn show_gives_age_of_last_update_in_locale() {
+        let now = parse_timestamp("2024-05-15T12:00:00").unwrap();
+        let mut repo = TaskRepository::default();
+        repo.new_task_at("Plop".to_string(), now - TimeDelta::days(3));
+        let task = repo.task_ref(1).unwrap();
+        for (locale, age) in [
+            (Locale::English, "3 days ago"),
+            (Locale::French, "il y a 3 jours"),
+        ] {
+            let mut out = Vec::new();
+            print_task_detail(task, locale, now, &mut out).unwrap();
+            let output = String::from_utf8(out).unwrap();
+            let expected = format!("Last update: {} ({})", task.updated_at, age);
+            assert!(output.lines().any(|line| line == expected), "{}", output);
+        }
+    }
+
+    #[test]
+    fn show_unknown_task_is_an_error() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        for (args, err) in [
+            (vec!["tasklist", "show", "2"], TaskError::NotFound(2)),
+            (
+                vec!["tasklist", "show"],
+                TaskError::MissingArgument("id of task to show"),
+            ),
+            (
+                vec!["tasklist", "show", "abc"],
+                TaskError::InvalidId("abc".to_string()),
+            ),
+        ] {
+            let args: Vec<String> = args.into_iter().map(String::from).collect();
+            assert_eq!(
+                handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+                Err(err)
+            );
+        }
+    }
+
+    #[test]
+    fn peek_prints_next_task() {
+        let mut repo = TaskRepository::default();
+        let mut out = Vec::new();
+        handle_peek_command(&repo, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Nothing pending\n");
+
+        for desc in ["Plop", "Plip", "Plap"] {
+            repo.new_task(desc.to_string());
+        }
+        repo.set_priority(2, Priority::High).unwrap();
+        let before = repo.clone();
+        let mut out = Vec::new();
+        handle_peek_command(&repo, &mut out).unwrap();
+        let output = String::from_utf8(out).unwrap();
+        assert!(output.starts_with("Task 2: \"Plip\""));
+        assert_eq!(output.lines().count(), 1);
+        assert_eq!(repo, before);
+    }
+
+    #[test]
+    fn export_markdown_checks_done_tasks() {
+        let mut repo = TaskRepository::default();
+        for desc in ["Plop", "Plip", "Plap"] {
+            repo.new_task(desc.to_string());
+        }
+        repo.set_status(2, TaskStatus::Done).unwrap();
+        repo.set_status(3, TaskStatus::InProgress).unwrap();
+        assert_eq!(
+            export_markdown(&repo),
+            "- [ ] #1 Plop\n- [x] #2 Plip\n- [ ] #3 Plap\n"
+        );
+        assert_eq!(export_markdown(&TaskRepository::default()), "");
+    }
+
+    #[test]
+    fn export_ical_lists_tasks_due_as_todos() {
+        let mut repo = TaskRepository::default();
+        for desc in ["Plop", "Plip, plap"] {
+            repo.new_task(desc.to_string());
+        }
+        let due_date = Local.with_ymd_and_hms(2024, 5, 1, 10, 30, 0).unwrap();
+        repo.set_due_date(2, Some(due_date)).unwrap();
+        repo.set_status(2, TaskStatus::Done).unwrap();
+        let updated_at = repo.task_ref(2).unwrap().updated_at;
+        let calendar = export_ical(&repo);
+        let mut lines = calendar.split_terminator("\r\n");
+        assert_eq!(lines.next(), Some("BEGIN:VCALENDAR"));
+        let todo: Vec<&str> = lines.skip_while(|line| *line != "BEGIN:VTODO").collect();
+        let dtstamp = format!("DTSTAMP:{}", updated_at.to_utc().format("%Y%m%dT%H%M%SZ"));
+        let due = format!("DUE:{}", due_date.to_utc().format("%Y%m%dT%H%M%SZ"));
+        assert_eq!(
+            todo,
+            [
+                "BEGIN:VTODO",
+                "UID:task-2@tasklist",
+                dtstamp.as_str(),
+                "SUMMARY:Plip\\, plap",
+                due.as_str(),
+                "STATUS:COMPLETED",
+                "END:VTODO",
+                "END:VCALENDAR",
+            ]
+        );
+        assert!(calendar.ends_with("END:VCALENDAR\r\n"));
+        assert!(!export_ical(&TaskRepository::default()).contains("VTODO"));
+    }
+
+    #[test]
+    fn usage_lists_every_command() {
+        let source = include_str!("main.rs");
+        let dispatch = &source[source.find("fn handle_command(").unwrap()..];
+        let dispatch = &dispatch[..dispatch.find("_ => Err").unwrap()];
+        let commands: Vec<&str> = dispatch
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix('"'))
+            .filter_map(|line| line.split('"').next())
+            .collect();
+        assert!(commands.contains(&"list"));
+        let usage = usage();
+        for command in commands {
+            assert!(
+                usage.contains(&format!("  {} ", command)),
+                "{} is missing from the help",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_command_prints_usage() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        let args: Vec<String> = ["tasklist", "plop"].map(String::from).to_vec();
+        let err = handle_command(&args, &mut repo, &ctx, &mut io::sink())
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("Unknown parameter plop\n"));
+        assert!(err.ends_with(&usage()));
+    }
+
+    #[test]
+    fn schema_is_valid_json() {
+        let mut out = Vec::new();
+        handle_schema_command(&mut out).unwrap();
+        let schema: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let output = String::from_utf8(out).unwrap();
+        assert!(output.contains("\"tasks\""));
+        assert!(schema["$defs"]["task"]["required"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::Value::from("id")));
+    }
+
+    #[test]
+    fn export_rejects_unknown_format() {
+        let repo = TaskRepository::default();
+        let args: Vec<String> = ["tasklist", "export", "--format", "csv"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            handle_export_command(&args, &repo, &mut io::sink()),
+            Err(TaskError::Invalid(
+                "Unsupported export format: csv".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn dump_to_file_then_restore_saves_the_tasks() {
+        let (tmp_dir, ctx) = setup();
+        let dump_path = tmp_dir.path().join("dump.json");
+        let dump_path = dump_path.to_str().unwrap();
         let mut repo = TaskRepository::default();
-        add_task(&mut repo, "TestTask".to_string());
-        let task = &repo.task(1);
-        assert_eq!(task.description, "TestTask");
-        assert_eq!(task.id, 1);
+        repo.new_task("Plop".to_string());
+        repo.new_task("Plip".to_string());
+        let args: Vec<String> = ["tasklist", "dump", "--out", dump_path]
+            .map(String::from)
+            .to_vec();
+        let mut out = Vec::new();
+        handle_command(&args, &mut repo, &ctx, &mut out).unwrap();
+        assert!(out.is_empty());
+
+        let mut other_repo = TaskRepository::default();
+        let args: Vec<String> = ["tasklist", "restore", dump_path]
+            .map(String::from)
+            .to_vec();
+        let mut out = Vec::new();
+        handle_command(&args, &mut other_repo, &ctx, &mut out).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("Restored 2 task(s)\n"));
+        assert_eq!(task_repository::load_repository(&ctx.task_file), Ok(repo));
     }
 
     #[test]
-    fn task_id_incremental() {
-        setup();
+    fn count_active_excludes_done_tasks() {
         let mut repo = TaskRepository::default();
-        repo.new_task(String::from("TestTask"));
-        repo.new_task(String::from("otherTask"));
-        let task2 = &repo.task(2);
-        assert_eq!(task2.description, "otherTask");
-        assert_eq!(task2.id, 2);
+        for desc in ["Plop", "Plip", "Plap"] {
+            repo.new_task(desc.to_string());
+        }
+        repo.set_status(1, TaskStatus::Done).unwrap();
+        repo.set_status(2, TaskStatus::InProgress).unwrap();
+        for (args, expected) in [
+            (vec!["tasklist", "count", "--active"], "2\n"),
+            (vec!["tasklist", "count"], "3\n"),
+        ] {
+            let args: Vec<String> = args.into_iter().map(String::from).collect();
+            let mut out = Vec::new();
+            handle_count_command(&args, &repo, &mut out).unwrap();
+            assert_eq!(String::from_utf8(out).unwrap(), expected);
+        }
     }
 
     #[test]
-    fn list_task() {
-        setup();
+    fn count_by_priority_on_mixed_repo() {
         let mut repo = TaskRepository::default();
-        repo.new_task(String::from("TestTask"));
-        repo.new_task(String::from("otherTask"));
-        assert_eq!(repo.task_count(), 2);
+        for desc in ["Plop", "Plip", "Plap"] {
+            repo.new_task(desc.to_string());
+        }
+        repo.set_priority(1, Priority::High).unwrap();
+        repo.set_priority(2, Priority::High).unwrap();
+        let args: Vec<String> = ["tasklist", "count", "--by-priority"]
+            .map(String::from)
+            .to_vec();
+        let mut out = Vec::new();
+        handle_count_command(&args, &repo, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Low: 0\nMedium: 1\nHigh: 2\n"
+        );
+
+        let args: Vec<String> = ["tasklist", "count", "--by-priority", "high"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            handle_count_command(&args, &repo, &mut io::sink()),
+            Err(TaskError::Invalid("Unexpected argument: high".to_string()))
+        );
     }
 
     #[test]
-    fn delete_task() {
-        setup();
+    fn count_with_status_counts_only_that_status() {
+        let mut repo = TaskRepository::default();
+        for desc in ["Plop", "Plip", "Plap"] {
+            repo.new_task(desc.to_string());
+        }
+        repo.set_status(1, TaskStatus::Done).unwrap();
+        repo.set_status(2, TaskStatus::Done).unwrap();
+        for (args, expected) in [
+            (vec!["tasklist", "count", "done"], "2\n"),
+            (vec!["tasklist", "count", "todo"], "1\n"),
+            (vec!["tasklist", "count", "in-progress"], "0\n"),
+        ] {
+            let args: Vec<String> = args.into_iter().map(String::from).collect();
+            let mut out = Vec::new();
+            handle_count_command(&args, &repo, &mut out).unwrap();
+            assert_eq!(String::from_utf8(out).unwrap(), expected);
+        }
+
+        let args: Vec<String> = ["tasklist", "count", "doing"].map(String::from).to_vec();
+        assert_eq!(
+            handle_count_command(&args, &repo, &mut io::sink()),
+            Err(TaskError::Invalid("Unexpected argument: doing".to_string()))
+        );
+    }
+
+    #[test]
+    fn search_without_match() {
+        let mut repo = TaskRepository::default();
+        repo.new_task("Buy milk".to_string());
+        let args: Vec<String> = ["tasklist", "search", "cheese"].map(String::from).to_vec();
+        let mut out = Vec::new();
+        handle_search_command(&args, &repo, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "No matching tasks\n");
+
+        let args: Vec<String> = ["tasklist", "search"].map(String::from).to_vec();
+        assert_eq!(
+            handle_search_command(&args, &repo, &mut io::sink()),
+            Err(TaskError::MissingArgument("search query"))
+        );
+    }
+
+    #[test]
+    fn list_marks_stale_tasks() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Fresh".to_string());
+        repo.new_task("Old".to_string());
+        repo.new_task("Ancient".to_string());
+        repo.task(1).unwrap().updated_at = Local::now() - TimeDelta::days(10);
+        repo.task(2).unwrap().updated_at = Local::now() - TimeDelta::days(40);
+        repo.task(3).unwrap().updated_at = Local::now() - TimeDelta::days(400);
+        let args: Vec<String> = ["tasklist", "list", "--stale-after", "30d"]
+            .map(String::from)
+            .to_vec();
+        let mut out = Vec::new();
+        handle_command(&args, &mut repo, &ctx, &mut out).unwrap();
+        let output = String::from_utf8(out).unwrap();
+
+        let line = |desc: &str| {
+            output
+                .lines()
+                .find(|line| line.contains(desc))
+                .unwrap()
+                .to_string()
+        };
+        assert!(!line("Fresh").starts_with("(stale)"));
+        assert!(line("Old").starts_with("(stale)"));
+        assert!(line("Ancient").starts_with("(stale)"));
+    }
+
+    #[test]
+    fn list_in_french_translates_headers_and_stale_marker() {
+        let now = Local::now();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Vieille".to_string());
+        repo.new_task("Sans date".to_string());
+        let task = repo.task(1).unwrap();
+        task.due_date = Some(now - TimeDelta::days(1));
+        task.updated_at = now - TimeDelta::days(40);
+        let args: Vec<String> = [
+            "tasklist",
+            "list",
+            "--group-by",
+            "due",
+            "--stale-after",
+            "30d",
+        ]
+        .map(String::from)
+        .to_vec();
+        let mut out = Vec::new();
+        handle_list_command(&args, &repo, Locale::French, &mut out).unwrap();
+        let output = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = output
+            .lines()
+            .map(|line| line.split(". Priority").next().unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "En retard:",
+                "(en sommeil) Task 1: \"Vieille\" Todo",
+                "Sans échéance:",
+                "Task 2: \"Sans date\" Todo",
+            ]
+        );
+    }
+
+    #[test]
+    fn list_without_threshold_marks_nothing() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Ancient".to_string());
+        repo.task(1).unwrap().updated_at = Local::now() - TimeDelta::days(400);
+        let args: Vec<String> = ["tasklist", "list"].map(String::from).to_vec();
+        let mut out = Vec::new();
+        handle_command(&args, &mut repo, &ctx, &mut out).unwrap();
+        assert!(!String::from_utf8(out).unwrap().contains("(stale)"));
+    }
+
+    #[test]
+    fn mutations_keep_created_at() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        let created_at = parse_timestamp("2024-01-15T08:30:00.123456789+02:00").unwrap();
+        repo.new_task_at("Plop".to_string(), created_at);
+        let saved_created_at = repo.task(1).unwrap().created_at.to_rfc3339();
+        for args in [
+            vec!["tasklist", "update", "1", "New desc"],
+            vec!["tasklist", "mark-in-progress", "1"],
+            vec!["tasklist", "set", "1", "status", "done"],
+            vec!["tasklist", "reopen", "1"],
+            vec!["tasklist", "tag", "1", "work"],
+            vec!["tasklist", "set-due", "1", "2024-02-01"],
+        ] {
+            let args: Vec<String> = args.into_iter().map(String::from).collect();
+            assert_eq!(
+                handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+                Ok(())
+            );
+            let task = repo.task(1).unwrap();
+            assert_eq!(task.created_at.to_rfc3339(), saved_created_at);
+            assert!(task.updated_at > created_at);
+        }
+        assert_eq!(repo.task(1).unwrap().description, "New desc");
+    }
+
+    #[test]
+    fn set_several_fields() {
+        let (_tmp_dir, ctx) = setup();
         let mut repo = TaskRepository::default();
         repo.new_task("Plop".to_string());
-        repo.new_task("Plip".to_string());
-        repo.delete(1);
-        assert_eq!(repo.task_count(), 1);
-        let task = repo.task(2);
-        assert_eq!(task.id, 2);
-        assert_eq!(task.description, String::from("Plip"));
+        for args in [
+            ["tasklist", "set", "1", "description", "New desc"],
+            ["tasklist", "set", "1", "status", "in-progress"],
+            ["tasklist", "set", "1", "priority", "high"],
+            ["tasklist", "set", "1", "due", "2024-05-01"],
+        ] {
+            let args: Vec<String> = args.map(String::from).to_vec();
+            assert_eq!(
+                handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+                Ok(())
+            );
+        }
+        let task = repo.task(1).unwrap();
+        assert_eq!(task.description, "New desc");
+        assert_eq!(task.status, TaskStatus::InProgress);
+        assert_eq!(task.priority, Priority::High);
+        assert_eq!(
+            task.due_date,
+            Some(parse_due_date("2024-05-01", Locale::English).unwrap())
+        );
+
+        let args: Vec<String> = ["tasklist", "set", "1", "due", "none"]
+            .map(String::from)
+            .to_vec();
+        handle_command(&args, &mut repo, &ctx, &mut io::sink()).unwrap();
+        assert_eq!(repo.task(1).unwrap().due_date, None);
+    }
+
+    #[test]
+    fn set_description_is_trimmed_and_cannot_be_blank() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        let args: Vec<String> = ["tasklist", "set", "1", "description", "   "]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Err(TaskError::Invalid(
+                "Description cannot be empty".to_string()
+            ))
+        );
+        assert_eq!(repo.task(1).unwrap().description, "Plop");
+
+        let args: Vec<String> = ["tasklist", "set", "1", "description", " Padded\t"]
+            .map(String::from)
+            .to_vec();
+        handle_command(&args, &mut repo, &ctx, &mut io::sink()).unwrap();
+        assert_eq!(repo.task(1).unwrap().description, "Padded");
+    }
+
+    #[test]
+    fn set_rejects_unknown_field_and_invalid_value() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        let args: Vec<String> = ["tasklist", "set", "1", "colour", "blue"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Err(TaskError::Invalid("Unknown field: colour".to_string()))
+        );
+        let args: Vec<String> = ["tasklist", "set", "1", "status", "later"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Err(TaskError::Invalid("Invalid status: later".to_string()))
+        );
+        for (field, value) in [("priority", "urgent"), ("due", "tomorrow")] {
+            let args: Vec<String> = ["tasklist", "set", "1", field, value]
+                .map(String::from)
+                .to_vec();
+            assert!(handle_command(&args, &mut repo, &ctx, &mut io::sink()).is_err());
+        }
+        let task = repo.task(1).unwrap();
         assert_eq!(task.status, TaskStatus::Todo);
+        assert_eq!(task.priority, Priority::Medium);
+        assert_eq!(task.due_date, None);
     }
 
     #[test]
     fn update_task_with_desc_by_id() {
-        setup();
+        let (_tmp_dir, ctx) = setup();
         let mut repo = TaskRepository::default();
         repo.new_task("Plop".to_string());
         repo.new_task("Plip".to_string());
-        update_task(&mut repo, 2, "New desc".to_string());
-        assert_eq!(repo.task(2).description, "New desc");
+        update_task(&mut repo, 2, "New desc".to_string(), &ctx).unwrap();
+        assert_eq!(repo.task(2).unwrap().description, "New desc");
+    }
+
+    #[test]
+    fn edit_replaces_description() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        let before = Local::now() - TimeDelta::days(1);
+        repo.task(1).unwrap().updated_at = before;
+        let args: Vec<String> = ["tasklist", "edit", "1"].map(String::from).to_vec();
+        let mut input = "A much longer description\n".as_bytes();
+        let mut out = Vec::new();
+        assert_eq!(
+            handle_edit_command(&args, &mut repo, &ctx, &mut input, &mut out),
+            Ok(())
+        );
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with("Current description: Plop\nNew description (empty to keep): "));
+        let task = repo.task(1).unwrap();
+        assert_eq!(task.description, "A much longer description");
+        assert!(task.updated_at > before);
+        let mut loaded_repo = task_repository::load_repository(&ctx.task_file).unwrap();
+        assert_eq!(
+            loaded_repo.task(1).unwrap().description,
+            "A much longer description"
+        );
+    }
+
+    #[test]
+    fn edit_trims_description_and_rejects_blank_one() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        let args: Vec<String> = ["tasklist", "edit", "1"].map(String::from).to_vec();
+        assert_eq!(
+            handle_edit_command(
+                &args,
+                &mut repo,
+                &ctx,
+                &mut "   \n".as_bytes(),
+                &mut io::sink()
+            ),
+            Err(TaskError::Invalid(
+                "Description cannot be empty".to_string()
+            ))
+        );
+        assert_eq!(repo.task(1).unwrap().description, "Plop");
+        assert!(!ctx.task_file.exists());
+
+        let mut input = "  Padded  \n".as_bytes();
+        handle_edit_command(&args, &mut repo, &ctx, &mut input, &mut io::sink()).unwrap();
+        assert_eq!(repo.task(1).unwrap().description, "Padded");
+    }
+
+    #[test]
+    fn edit_with_empty_input_keeps_description() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        let before = repo.clone();
+        let args: Vec<String> = ["tasklist", "edit", "1"].map(String::from).to_vec();
+        for answer in ["\n", ""] {
+            let mut out = Vec::new();
+            assert_eq!(
+                handle_edit_command(&args, &mut repo, &ctx, &mut answer.as_bytes(), &mut out),
+                Ok(())
+            );
+            assert!(String::from_utf8(out)
+                .unwrap()
+                .ends_with("Description unchanged\n"));
+            assert_eq!(repo, before);
+        }
+        assert!(!ctx.task_file.exists());
+
+        let args: Vec<String> = ["tasklist", "edit", "2"].map(String::from).to_vec();
+        assert_eq!(
+            handle_edit_command(
+                &args,
+                &mut repo,
+                &ctx,
+                &mut "x\n".as_bytes(),
+                &mut io::sink()
+            ),
+            Err(TaskError::NotFound(2))
+        );
+    }
+
+    #[test]
+    fn update_task_refreshes_updated_at() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        let before = Local::now() - TimeDelta::days(1);
+        repo.task(1).unwrap().updated_at = before;
+        update_task(&mut repo, 1, "New desc".to_string(), &ctx).unwrap();
+        assert!(repo.task(1).unwrap().updated_at > before);
+    }
+
+    #[test]
+    fn mark_in_progress_refreshes_updated_at() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        let before = Local::now() - TimeDelta::days(1);
+        repo.task(1).unwrap().updated_at = before;
+        mark_in_progress(&mut repo, 1, &ctx).unwrap();
+        assert!(repo.task(1).unwrap().updated_at > before);
     }
 
     #[test]
     fn update_inprogress() {
-        setup();
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        mark_in_progress(&mut repo, 1, &ctx).unwrap();
+        assert_eq!(repo.task(1).unwrap().status, TaskStatus::InProgress);
+    }
+
+    #[test]
+    fn list_to_closed_pipe_succeeds() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        for i in 0..100 {
+            repo.new_task(format!("Task {}", i));
+        }
+        for args in [vec!["tasklist", "list"], vec!["tasklist", "list", "todo"]] {
+            let args: Vec<String> = args.into_iter().map(String::from).collect();
+            assert_eq!(
+                handle_command(&args, &mut repo, &ctx, &mut ClosedPipe),
+                Ok(())
+            );
+        }
+    }
+
+    #[test]
+    fn mutation_saves_despite_closed_pipe() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        let args: Vec<String> = ["tasklist", "add", "Plop"].map(String::from).to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut ClosedPipe),
+            Ok(())
+        );
+        assert_eq!(repo.task_count(), 1);
+    }
+
+    #[test]
+    fn mark_todo_resets_status() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        repo.task(1).unwrap().status = TaskStatus::Done;
+        let before = Local::now() - TimeDelta::days(1);
+        repo.task(1).unwrap().updated_at = before;
+        assert_eq!(mark_todo(&mut repo, 1, &ctx), Ok(()));
+        assert_eq!(repo.task(1).unwrap().status, TaskStatus::Todo);
+        assert!(repo.task(1).unwrap().updated_at > before);
+    }
+
+    #[test]
+    fn mark_todo_unknown_task() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        assert_eq!(mark_todo(&mut repo, 3, &ctx), Err(TaskError::NotFound(3)));
+    }
+
+    #[test]
+    fn print_task_shows_last_update() {
+        let mut repo = TaskRepository::default();
+        let created_at = parse_timestamp("2024-01-15T08:30:00").unwrap();
+        let updated_at = parse_timestamp("2024-03-20T17:45:00").unwrap();
+        repo.new_task_at("Plop".to_string(), created_at);
+        repo.task(1).unwrap().updated_at = updated_at;
+        let mut out = Vec::new();
+        print_task(repo.task(1).unwrap(), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "Task 1: \"Plop\" Todo. Priority: Medium. Created at: {}. Last update: {}\n",
+                created_at, updated_at
+            )
+        );
+    }
+
+    #[test]
+    fn print_task_shows_completion_time() {
+        let mut repo = TaskRepository::default();
+        let created_at = parse_timestamp("2024-01-15T08:30:00").unwrap();
+        let completed_at = parse_timestamp("2024-03-20T17:45:00").unwrap();
+        repo.new_task_at("Plop".to_string(), created_at);
+        let task = repo.task(1).unwrap();
+        task.status = TaskStatus::Done;
+        task.completed_at = Some(completed_at);
+        let mut out = Vec::new();
+        print_task(task, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "Task 1: \"Plop\" Done. Priority: Medium. Created at: {}. Last update: {}. Completed at: {}\n",
+                created_at, created_at, completed_at
+            )
+        );
+    }
+
+    #[test]
+    fn non_numeric_id_is_rejected() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        for args in [
+            vec!["tasklist", "delete", "abc"],
+            vec!["tasklist", "update", "abc", "New desc"],
+            vec!["tasklist", "mark-in-progress", "abc"],
+        ] {
+            let args: Vec<String> = args.into_iter().map(String::from).collect();
+            assert_eq!(
+                handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+                Err(TaskError::InvalidId("abc".to_string()))
+            );
+        }
+        assert_eq!(repo.task_count(), 1);
+    }
+
+    #[test]
+    fn unknown_task_id_is_reported() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        for args in [
+            vec!["tasklist", "update", "999", "New desc"],
+            vec!["tasklist", "mark-in-progress", "999"],
+        ] {
+            let args: Vec<String> = args.into_iter().map(String::from).collect();
+            assert_eq!(
+                handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+                Err(TaskError::NotFound(999))
+            );
+        }
+    }
+
+    #[test]
+    fn file_option_overrides_env() {
+        let args: Vec<String> = ["tasklist", "--file", "other.json", "add", "Plop"]
+            .map(String::from)
+            .to_vec();
+        let (options, command_args) = parse_global_options(&args).unwrap();
+        assert_eq!(options.task_file, Some(PathBuf::from("other.json")));
+        assert_eq!(command_args, ["tasklist", "add", "Plop"].map(String::from));
+        let home = || Some(OsString::from("/home/plop"));
+        assert_eq!(
+            resolve_task_file(options.task_file, Some(OsString::from("env.json")), home()),
+            Some(PathBuf::from("other.json"))
+        );
+        assert_eq!(
+            resolve_task_file(None, Some(OsString::from("env.json")), home()),
+            Some(PathBuf::from("env.json"))
+        );
+        assert_eq!(resolve_task_file(None, None, None), None);
+    }
+
+    #[test]
+    fn reuse_ids_option() {
+        let args: Vec<String> = ["tasklist", "--reuse-ids", "add", "plop"]
+            .map(String::from)
+            .to_vec();
+        let (options, command_args) = parse_global_options(&args).unwrap();
+        assert!(options.reuse_ids);
+        assert_eq!(command_args, ["tasklist", "add", "plop"].map(String::from));
+    }
+
+    /// Runs the application on a task file with the given arguments after the program name.
+    ///
+    /// Returns the exit code and what was written to stdout and to stderr.
+    fn run_on(task_file: &Path, args: &[&str]) -> (ExitCode, String, String) {
+        let mut all_args = vec!["tasklist", "--file", task_file.to_str().unwrap()];
+        all_args.extend(args);
+        let all_args: Vec<String> = all_args.into_iter().map(String::from).collect();
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let code = run(&all_args, &mut out, &mut err);
+        (
+            code,
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(err).unwrap(),
+        )
+    }
+
+    #[test]
+    fn undo_after_purge_removing_nothing_brings_back_deleted_task() {
+        let tmp_dir = TempDir::new().unwrap();
+        let task_file = tmp_dir.path().join("tasks.json");
+        for args in [
+            &["add", "keep"][..],
+            &["add", "victim"],
+            &["delete", "2", "--yes"],
+            &["purge"],
+            &["undo"],
+        ] {
+            let (code, out, _) = run_on(&task_file, args);
+            assert_eq!(code, ExitCode::SUCCESS, "{:?}: {}", args, out);
+        }
+        let repo = task_repository::load_repository(&task_file).unwrap();
+        let descriptions: Vec<&str> = repo.tasks().map(|t| t.description.as_str()).collect();
+        assert_eq!(descriptions, ["keep", "victim"]);
+    }
+
+    #[test]
+    fn output_lost_on_full_disk_fails_run() {
+        let tmp_dir = TempDir::new().unwrap();
+        let task_file = tmp_dir.path().join("tasks.json");
+        run_on(&task_file, &["add", "Plop"]);
+        for args in [
+            &["list", "--json"][..],
+            &["dump"],
+            &["count"],
+            &["help"],
+            &[],
+        ] {
+            let mut all_args = vec!["tasklist", "--file", task_file.to_str().unwrap()];
+            all_args.extend(args);
+            let all_args: Vec<String> = all_args.into_iter().map(String::from).collect();
+            let mut err = Vec::new();
+            let code = run(&all_args, &mut FullDisk, &mut err);
+            assert_eq!(code, ExitCode::from(1), "{:?}", args);
+            let err = String::from_utf8(err).unwrap();
+            assert!(
+                err.contains("Failed to write output"),
+                "{:?}: {}",
+                args,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn flush_failure_fails_unless_output_was_closed() {
+        let mut err = Vec::new();
+        let code = exit_after_output(FullDisk.flush(), ExitCode::SUCCESS, &mut err);
+        assert_eq!(code, ExitCode::from(1));
+        assert!(String::from_utf8(err)
+            .unwrap()
+            .starts_with("Failed to write output"));
+
+        let mut err = Vec::new();
+        let code = exit_after_output(ClosedPipe.flush(), ExitCode::SUCCESS, &mut err);
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert!(err.is_empty());
+    }
+
+    #[test]
+    fn list_json_output_of_run_is_only_json() {
+        let (tmp_dir, _ctx) = setup();
+        let task_file = tmp_dir.path().join("tasks.json");
+        run_on(&task_file, &["add", "Plop"]);
+        run_on(&task_file, &["add", "Plip"]);
+        run_on(&task_file, &["set", "2", "status", "done"]);
+
+        let (code, out, err) = run_on(&task_file, &["list", "todo", "--json"]);
+        assert_eq!(code, ExitCode::from(0));
+        let tasks: Vec<Task> = serde_json::from_str(&out).unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].description, "Plop");
+        assert_eq!(err, format!("Reading tasks from {}\n", task_file.display()));
+    }
+
+    #[test]
+    fn recent_done_lists_only_completed_tasks() {
+        let tmp_dir = TempDir::new().unwrap();
+        let task_file = tmp_dir.path().join("tasks.json");
+        assert_eq!(
+            run_on(&task_file, &["recent-done"]).1,
+            "No task has been completed yet.\n"
+        );
+        for desc in ["Plop", "Plip", "Plap"] {
+            run_on(&task_file, &["add", desc]);
+        }
+        run_on(&task_file, &["set", "1", "status", "done"]);
+        run_on(&task_file, &["set", "3", "status", "done"]);
+
+        let (code, out, _err) = run_on(&task_file, &["recent-done", "1"]);
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(out.lines().count(), 1);
+        assert!(out.starts_with("Task 3: \"Plap\" Done."), "{}", out);
+        let (_, out, _) = run_on(&task_file, &["recent-done"]);
+        assert!(!out.contains("Plip"), "{}", out);
+        assert_eq!(out.lines().count(), 2);
+    }
+
+    #[test]
+    fn saved_task_file_reported_on_stderr() {
+        let tmp_dir = TempDir::new().unwrap();
+        let task_file = tmp_dir.path().join("tasks.json");
+        let (code, out, err) = run_on(&task_file, &["add", "Plop"]);
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert!(!out.contains("tasks.json"), "{}", out);
+        assert_eq!(
+            err,
+            format!(
+                "Reading tasks from {0}\nSaved tasks to {0}\n",
+                task_file.display()
+            )
+        );
+        let (_, _, err) = run_on(&task_file, &["show", "1"]);
+        assert!(!err.contains("Saved tasks to"), "{}", err);
+    }
+
+    #[test]
+    fn count_output_of_run_is_only_the_number() {
+        let (tmp_dir, _ctx) = setup();
+        let task_file = tmp_dir.path().join("tasks.json");
+        assert_eq!(run_on(&task_file, &["count"]).1, "0\n");
+        run_on(&task_file, &["add", "Plop"]);
+        run_on(&task_file, &["add", "Plip"]);
+
+        let (code, out, _err) = run_on(&task_file, &["count"]);
+        assert_eq!(code, ExitCode::from(0));
+        assert_eq!(out, "2\n");
+    }
+
+    #[test]
+    fn count_active_output_of_run_is_only_the_number() {
+        let (tmp_dir, _ctx) = setup();
+        let task_file = tmp_dir.path().join("tasks.json");
+        for desc in ["Plop", "Plip", "Plap"] {
+            run_on(&task_file, &["add", desc]);
+        }
+        run_on(&task_file, &["set", "1", "status", "done"]);
+
+        let (code, out, _err) = run_on(&task_file, &["count", "--active"]);
+        assert_eq!(code, ExitCode::from(0));
+        assert_eq!(out, "2\n");
+    }
+
+    #[test]
+    fn list_ids_output_of_run_is_only_ids() {
+        let (tmp_dir, _ctx) = setup();
+        let task_file = tmp_dir.path().join("tasks.json");
+        for desc in ["Plop", "Plip", "Plap"] {
+            run_on(&task_file, &["add", desc]);
+        }
+        run_on(&task_file, &["set", "2", "status", "done"]);
+
+        let (code, out, _err) = run_on(&task_file, &["list", "todo", "--ids"]);
+        assert_eq!(code, ExitCode::from(0));
+        assert_eq!(out, "1\n3\n");
+    }
+
+    #[test]
+    fn dump_to_stdout_then_restore_through_run() {
+        let (tmp_dir, _ctx) = setup();
+        let task_file = tmp_dir.path().join("tasks.json");
+        run_on(&task_file, &["add", "Plop", "--tag", "work"]);
+        run_on(&task_file, &["add", "Plip", "high"]);
+        let (code, out, _err) = run_on(&task_file, &["dump"]);
+        assert_eq!(code, ExitCode::from(0));
+        let dump_file = tmp_dir.path().join("dump.json");
+        fs::write(&dump_file, out).unwrap();
+
+        let other_file = tmp_dir.path().join("other.json");
+        let (code, _out, _err) = run_on(&other_file, &["restore", dump_file.to_str().unwrap()]);
+        assert_eq!(code, ExitCode::from(0));
+        assert_eq!(
+            task_repository::load_repository(&other_file),
+            task_repository::load_repository(&task_file)
+        );
+    }
+
+    #[test]
+    fn profile_timing_prints_phases_to_stderr() {
+        let (tmp_dir, _ctx) = setup();
+        let task_file = tmp_dir.path().join("tasks.json");
+        for (extra_args, timed) in [(vec!["--profile-timing"], true), (vec![], false)] {
+            let mut args = extra_args;
+            args.extend(["add", "Plop"]);
+            let (code, out, err) = run_on(&task_file, &args);
+            assert_eq!(code, ExitCode::from(0));
+            assert!(!out.contains("load: "));
+            let mut lines = err.lines();
+            assert!(lines.next().unwrap().starts_with("Reading tasks from"));
+            assert!(lines.next().unwrap().starts_with("Saved tasks to"));
+            if timed {
+                let line = lines.next().unwrap();
+                let phases: Vec<&str> = line
+                    .split(", ")
+                    .map(|phase| phase.split(':').next().unwrap())
+                    .collect();
+                assert_eq!(phases, ["load", "command", "save"], "{}", line);
+                assert!(line.ends_with("ms"), "{}", line);
+            }
+            assert_eq!(lines.next(), None);
+        }
+        let repo = task_repository::load_repository(&task_file).unwrap();
+        assert_eq!(repo.task_count(), 2);
+    }
+
+    #[test]
+    fn task_file_locked_only_while_saving() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        add_task(&mut repo, NewTask::new("First"), &ctx).unwrap();
+
+        let lock = task_repository::lock_file(&ctx.task_file).unwrap();
+        let (code, out, _) = run_on(&ctx.task_file, &["list"]);
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert!(out.contains("First"), "{}", out);
+        let err = add_task(&mut repo, NewTask::new("Second"), &ctx).unwrap_err();
+        assert!(
+            err.to_string().ends_with("is locked by another process"),
+            "{}",
+            err
+        );
+
+        drop(lock);
+        add_task(&mut repo, NewTask::new("Third"), &ctx).unwrap();
+        assert_eq!(
+            task_repository::load_repository(&ctx.task_file)
+                .unwrap()
+                .task_count(),
+            3
+        );
+    }
+
+    #[test]
+    fn save_fails_if_task_file_changed_since_read() {
+        let (_tmp_dir, ctx) = setup();
+        let mut repo = TaskRepository::default();
+        add_task(&mut repo, NewTask::new("Mine"), &ctx).unwrap();
+        let (code, _, _) = run_on(&ctx.task_file, &["add", "Theirs"]);
+        assert_eq!(code, ExitCode::SUCCESS);
+
+        let err = add_task(&mut repo, NewTask::new("Lost"), &ctx).unwrap_err();
+        assert!(
+            err.to_string().contains("was changed by another command"),
+            "{}",
+            err
+        );
+        let saved = task_repository::load_repository(&ctx.task_file).unwrap();
+        let descriptions: Vec<&str> = saved.tasks().map(|t| t.description.as_str()).collect();
+        assert_eq!(descriptions, ["Mine", "Theirs"]);
+    }
+
+    #[test]
+    fn readonly_option() {
+        let args: Vec<String> = ["tasklist", "--readonly", "list"]
+            .map(String::from)
+            .to_vec();
+        let (options, command_args) = parse_global_options(&args).unwrap();
+        assert!(options.readonly);
+        assert_eq!(command_args, ["tasklist", "list"].map(String::from));
+    }
+
+    #[test]
+    fn locale_option() {
+        let args: Vec<String> = ["tasklist", "--locale", "fr", "list"]
+            .map(String::from)
+            .to_vec();
+        let (options, command_args) = parse_global_options(&args).unwrap();
+        assert_eq!(options.locale, Some(Locale::French));
+        assert_eq!(command_args, ["tasklist", "list"].map(String::from));
+
+        let args: Vec<String> = ["tasklist", "--locale", "de", "list"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            parse_global_options(&args).map(|_| ()),
+            Err(TaskError::Invalid("Unknown locale: de".to_string()))
+        );
+    }
+
+    #[test]
+    fn set_due_through_run_reads_weekdays_of_the_locale() {
+        let tmp_dir = TempDir::new().unwrap();
+        let task_file = tmp_dir.path().join("tasks.json");
+        run_on(&task_file, &["add", "Plop"]);
+        let (code, _, _) = run_on(&task_file, &["--locale", "fr", "set-due", "1", "vendredi"]);
+        assert_eq!(code, ExitCode::SUCCESS);
+        let friday = next_weekday(Weekday::Fri, Local::now().date_naive()).unwrap();
+        let mut repo = task_repository::load_repository(&task_file).unwrap();
+        assert_eq!(repo.task(1).unwrap().due_date, end_of_day(friday));
+    }
+
+    #[test]
+    fn readonly_refuses_to_save() {
+        let (_tmp_dir, mut ctx) = setup();
+        let mut repo = TaskRepository::default();
+        repo.new_task("Plop".to_string());
+        save_repository(&mut repo, &ctx).unwrap();
+        let content = fs::read(&ctx.task_file).unwrap();
+
+        ctx.readonly = true;
+        let mut repo = task_repository::load_repository(&ctx.task_file).unwrap();
+        let args: Vec<String> = ["tasklist", "add", "Plap"].map(String::from).to_vec();
+        let mut out = Vec::new();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut out),
+            Err(TaskError::Invalid("Repository is read-only".to_string()))
+        );
+        assert!(out.is_empty());
+        assert_eq!(fs::read(&ctx.task_file).unwrap(), content);
+        assert!(!task_repository::backup_path(&ctx.task_file).exists());
+
+        let args: Vec<String> = ["tasklist", "list"].map(String::from).to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn layout_option() {
+        let args: Vec<String> = ["tasklist", "--layout", "array", "list"]
+            .map(String::from)
+            .to_vec();
+        let (options, command_args) = parse_global_options(&args).unwrap();
+        assert_eq!(options.layout, Some(FileLayout::Array));
+        assert!(!options.reuse_ids);
+        assert_eq!(command_args, ["tasklist", "list"].map(String::from));
+        let args: Vec<String> = ["tasklist", "--layout", "flat", "list"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            parse_global_options(&args),
+            Err(TaskError::Invalid("Unknown layout: flat".to_string()))
+        );
+    }
+
+    #[test]
+    fn task_file_defaults_to_home() {
+        assert_eq!(
+            resolve_task_file(None, None, Some(OsString::from("/home/plop"))),
+            Some(PathBuf::from("/home/plop/.tasklist.json"))
+        );
+    }
+
+    #[test]
+    fn save_creates_parent_dirs_when_asked() {
+        let tmp_dir = TempDir::new().unwrap();
+        let task_file = tmp_dir.path().join("home").join(".tasklist.json");
         let mut repo = TaskRepository::default();
         repo.new_task("Plop".to_string());
-        mark_in_progress(&mut repo, 1);
-        assert_eq!(repo.task(1).status, TaskStatus::InProgress);
+        let ctx = Context {
+            task_file: task_file.clone(),
+            create_parent_dirs: false,
+            readonly: false,
+            save_time: Cell::default(),
+            saves: Cell::default(),
+            loaded_version: Cell::default(),
+            locale: Locale::default(),
+            confirm_threshold: DEFAULT_CONFIRM_THRESHOLD,
+        };
+        assert!(save_repository(&mut repo, &ctx).is_err());
+        let ctx = Context {
+            task_file: task_file.clone(),
+            create_parent_dirs: true,
+            readonly: false,
+            save_time: Cell::default(),
+            saves: Cell::default(),
+            loaded_version: Cell::default(),
+            locale: Locale::default(),
+            confirm_threshold: DEFAULT_CONFIRM_THRESHOLD,
+        };
+        assert_eq!(save_repository(&mut repo, &ctx), Ok(()));
+        assert!(task_file.exists());
+    }
+
+    #[test]
+    fn ensure_dir_creates_parent_directories() {
+        let tmp_dir = TempDir::new().unwrap();
+        let task_file = tmp_dir.path().join("a").join("b").join("tasks.json");
+        let args: Vec<String> = vec![
+            "tasklist".to_string(),
+            "--ensure-dir".to_string(),
+            "--file".to_string(),
+            task_file.display().to_string(),
+            "add".to_string(),
+            "Plop".to_string(),
+        ];
+        let (options, args) = parse_global_options(&args).unwrap();
+        assert!(options.ensure_dir);
+        let ctx = Context {
+            task_file: options.task_file.unwrap(),
+            create_parent_dirs: options.ensure_dir,
+            readonly: false,
+            save_time: Cell::default(),
+            saves: Cell::default(),
+            loaded_version: Cell::default(),
+            locale: Locale::default(),
+            confirm_threshold: DEFAULT_CONFIRM_THRESHOLD,
+        };
+        let mut repo = TaskRepository::default();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Ok(())
+        );
+        assert!(tmp_dir.path().join("a").join("b").is_dir());
+        assert_eq!(
+            task_repository::load_repository(&task_file)
+                .unwrap()
+                .task_count(),
+            1
+        );
+    }
+
+    #[test]
+    fn short_file_option_only_before_command() {
+        let args: Vec<String> = ["tasklist", "-f", "other.json", "add", "-f"]
+            .map(String::from)
+            .to_vec();
+        let (options, command_args) = parse_global_options(&args).unwrap();
+        assert_eq!(options.task_file, Some(PathBuf::from("other.json")));
+        assert_eq!(command_args, ["tasklist", "add", "-f"].map(String::from));
+
+        let args: Vec<String> = ["tasklist", "--file"].map(String::from).to_vec();
+        assert!(parse_global_options(&args).is_err());
+    }
+
+    #[test]
+    fn command_saves_to_explicit_task_file() {
+        let tmp_dir = TempDir::new().unwrap();
+        let ctx = Context {
+            task_file: tmp_dir.path().join("explicit.json"),
+            create_parent_dirs: false,
+            readonly: false,
+            save_time: Cell::default(),
+            saves: Cell::default(),
+            loaded_version: Cell::default(),
+            locale: Locale::default(),
+            confirm_threshold: DEFAULT_CONFIRM_THRESHOLD,
+        };
+        let mut repo = TaskRepository::default();
+        let args: Vec<String> = ["tasklist", "add", "Plop"].map(String::from).to_vec();
+        assert_eq!(
+            handle_command(&args, &mut repo, &ctx, &mut io::sink()),
+            Ok(())
+        );
+        let loaded_repo = task_repository::load_repository(&ctx.task_file).unwrap();
+        assert_eq!(loaded_repo, repo);
     }
 
     #[test]
     fn save_load_repo() {
-        setup();
+        let (_tmp_dir, ctx) = setup();
         let mut repo = TaskRepository::default();
         repo.new_task("Plop".to_string());
         repo.new_task("Plip".to_string());
-        mark_in_progress(&mut repo, 1);
+        mark_in_progress(&mut repo, 1, &ctx).unwrap();
         let tmp_dir = TempDir::new().unwrap();
         let tmp_file = tmp_dir.path().join(Path::new("tmp_file.txt"));
-        task_repository::save_repository(&mut repo, &tmp_file);
-        let loaded_repo = task_repository::load_repository(&tmp_file);
+        task_repository::save_repository(&mut repo, &tmp_file).unwrap();
+        let loaded_repo = task_repository::load_repository(&tmp_file).unwrap();
         assert_eq!(repo, loaded_repo);
     }
 }
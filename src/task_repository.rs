@@ -1,36 +1,215 @@
+use crate::error::TaskError;
 use chrono::{DateTime, Local};
-use serde::{Deserialize, Serialize};
-use std::collections::hash_map::Values;
-use std::collections::HashMap;
+use serde::de::{SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Borrow;
+use std::cell::OnceCell;
+use std::cmp::Reverse;
+use std::collections::btree_map::Values;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{Display, Formatter};
 use std::fs;
 use std::fs::OpenOptions;
-use std::io::{BufReader, Write};
-use std::path::Path;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::ops::{Deref, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+use tempfile::Builder;
 
 /// Represents the status of a task.
-#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+///
+/// Statuses are saved with the spelling of the command line (`todo`, `in-progress`, `done`).
+/// The variant names written by older versions, and `in_progress`, are still read.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Serialize, Deserialize)]
 pub enum TaskStatus {
+    #[serde(rename = "todo", alias = "Todo")]
     Todo,
+    #[serde(rename = "in-progress", alias = "InProgress", alias = "in_progress")]
     InProgress,
+    #[serde(rename = "done", alias = "Done")]
     Done,
 }
 
-/// Represents a task with an ID, description, status, and timestamps.
+/// Represents how urgent a task is, ordered from the least to the most urgent.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Priority {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+/// A tag of a task, saved as a plain string.
+///
+/// Its text is shared rather than copied, so that the tasks of a repository carrying the same
+/// tag all point to a single string. It compares equal to the text it holds.
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Clone)]
+pub struct Tag(Rc<str>);
+
+/// Represents a task with an ID, description, status, priority, and timestamps.
 #[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub struct Task {
     pub id: i32,
     pub description: String,
     pub status: TaskStatus,
+    /// Files written before priorities existed have no such field, their tasks are `Medium`.
+    #[serde(default)]
+    pub priority: Priority,
     pub created_at: DateTime<Local>,
     pub updated_at: DateTime<Local>,
+    /// Files written before due dates existed have no such field, their tasks have none.
+    #[serde(default)]
+    pub due_date: Option<DateTime<Local>>,
+    /// Files written before tags existed have no such field, their tasks have none.
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+    /// When the task was last marked done, `None` while it is not done.
+    #[serde(default)]
+    pub completed_at: Option<DateTime<Local>>,
+    /// The items of the checklist of the task, each with whether it is checked.
+    /// Files written before checklists existed have no such field, their tasks have none.
+    #[serde(default)]
+    pub checklist: Vec<(String, bool)>,
+}
+
+/// The shape of the JSON document a task file holds.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum FileLayout {
+    /// An object whose `tasks` field holds the array of tasks.
+    #[default]
+    Wrapped,
+    /// A bare array of tasks.
+    Array,
 }
 
 /// A repository for managing tasks, including a map of tasks and the last assigned ID.
+///
+/// Tasks are kept sorted by ID, so iterating over them is deterministic.
 #[derive(Clone, PartialEq, Debug, Default)]
 pub struct TaskRepository {
-    tasks: HashMap<i32, Task>,
+    tasks: BTreeMap<i32, Task>,
     last_id: i32,
+    /// The layout the repository was loaded from, and is saved with.
+    layout: FileLayout,
+    /// Whether new tasks take the lowest free ID instead of the one after `last_id`.
+    reuse_ids: bool,
+    /// The index used by `search`, dropped whenever a task may have changed.
+    search_index: SearchIndex,
+    /// The tags carried by the tasks, each stored once.
+    tag_pool: TagPool,
+}
+
+/// Maps each lowercased word of the task descriptions to the IDs of the tasks using it, in
+/// ascending order.
+///
+/// It is a cache built on the first search, so it is ignored when comparing repositories.
+#[derive(Clone, Debug, Default)]
+struct SearchIndex(OnceCell<HashMap<String, Vec<i32>>>);
+
+impl PartialEq for SearchIndex {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// The distinct tags of the tasks of a repository, to hand out a shared copy of each.
+///
+/// A tag carried by many tasks then costs one allocation instead of one per task, each task
+/// only holding a pointer to it. Tags are kept once no task carries them anymore, which costs
+/// little as a repository lives for a single command. Only how tags are stored depends on it,
+/// so it is ignored when comparing repositories.
+#[derive(Clone, Debug, Default)]
+struct TagPool(HashSet<Tag>);
+
+impl PartialEq for TagPool {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl TagPool {
+    /// Returns the shared copy of a tag, adding it to the pool if it is not there yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `tag` - The text of the tag.
+    ///
+    /// # Returns
+    ///
+    /// A `Tag` sharing its text with every other copy of the same tag handed out by the pool.
+    fn intern(&mut self, tag: &str) -> Tag {
+        if let Some(tag) = self.0.get(tag) {
+            return tag.clone();
+        }
+        let tag = Tag::from(tag);
+        self.0.insert(tag.clone());
+        tag
+    }
+}
+
+impl Tag {
+    /// Returns the text of the tag.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for Tag {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for Tag {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Tag {
+    fn from(tag: &str) -> Self {
+        Tag(Rc::from(tag))
+    }
+}
+
+impl PartialEq<str> for Tag {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Tag {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl PartialEq<String> for Tag {
+    fn eq(&self, other: &String) -> bool {
+        *self.0 == **other
+    }
+}
+
+impl Display for Tag {
+    /// Formats the `Tag` as its text.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for Tag {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Tag {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(|tag| Tag::from(tag.as_str()))
+    }
 }
 
 impl Display for TaskStatus {
@@ -44,6 +223,69 @@ impl Display for TaskStatus {
     }
 }
 
+impl FromStr for TaskStatus {
+    type Err = TaskError;
+
+    /// Parses a status spelled as on the command line, in any case.
+    ///
+    /// In progress can also be spelled `inprogress`, `in_progress` or `wip`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "todo" => Ok(TaskStatus::Todo),
+            "in-progress" | "inprogress" | "in_progress" | "wip" => Ok(TaskStatus::InProgress),
+            "done" => Ok(TaskStatus::Done),
+            _ => Err(TaskError::Invalid(format!("Unknown status: {}", s))),
+        }
+    }
+}
+
+impl Task {
+    /// Records that the task has just been modified.
+    ///
+    /// Every mutation goes through this so that only `updated_at` changes, never `created_at`.
+    pub fn touch_updated(&mut self) {
+        self.updated_at = Local::now();
+    }
+
+    /// Changes the status of the task, recording when it becomes done.
+    ///
+    /// Moving to `Done` sets `completed_at` unless the task was already done, moving to any
+    /// other status clears it.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - The new status of the task.
+    pub fn change_status(&mut self, status: TaskStatus) {
+        self.completed_at = match status {
+            TaskStatus::Done if self.status == TaskStatus::Done => self.completed_at,
+            TaskStatus::Done => Some(Local::now()),
+            TaskStatus::Todo | TaskStatus::InProgress => None,
+        };
+        self.status = status;
+        self.touch_updated();
+    }
+
+    /// Tells whether the task is not done yet although its due date is past.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The time past which due tasks are overdue.
+    pub fn is_overdue(&self, now: DateTime<Local>) -> bool {
+        self.status != TaskStatus::Done && self.due_date.is_some_and(|due_date| due_date < now)
+    }
+}
+
+impl Display for Priority {
+    /// Formats the `Priority` for display.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Priority::Low => write!(f, "Low"),
+            Priority::Medium => write!(f, "Medium"),
+            Priority::High => write!(f, "High"),
+        }
+    }
+}
+
 /// A struct used for serializing and deserializing `TaskRepository`
 /// In `TaskRepository` `Task`s objects are stored in a hashmap
 /// Serializing a hash map in json produce a map <id,task>
@@ -54,44 +296,272 @@ struct TaskRepositoryForSerialization {
     tasks: Vec<Task>,
 }
 
-impl TaskRepository {
-    /// Creates a `TaskRepository` from a `TaskRepositoryForSerialization` object.
+/// Collects the tasks of a task file by ID as they are read, noting the IDs read twice.
+///
+/// Deserializing it from the `tasks` array inserts each task as soon as it is parsed, so the
+/// whole list is never held twice in memory.
+#[derive(Default)]
+struct TaskLoader {
+    repo: TaskRepository,
+    duplicates: Vec<i32>,
+}
+
+impl TaskLoader {
+    /// Adds a loaded task, keeping `last_id` at the highest ID read and sharing its tags with
+    /// the tasks already loaded.
     ///
     /// # Arguments
     ///
-    /// * `object` - A `TaskRepositoryForSerialization` object.
+    /// * `task` - The task read from the file.
+    fn insert(&mut self, mut task: Task) {
+        let id = task.id;
+        for tag in &mut task.tags {
+            *tag = self.repo.tag_pool.intern(tag);
+        }
+        self.repo.last_id = self.repo.last_id.max(id);
+        if self.repo.tasks.insert(id, task).is_some() && !self.duplicates.contains(&id) {
+            self.duplicates.push(id);
+        }
+    }
+
+    /// Returns the loaded repository.
     ///
     /// # Returns
     ///
-    /// A `TaskRepository` instance.
-    fn from_serialization(object: TaskRepositoryForSerialization) -> Self {
-        let mut task_repository = TaskRepository::default();
-        let mut max_id = 0;
-        for task in object.tasks {
-            if task.id > max_id {
-                max_id = task.id
+    /// The `TaskRepository` holding every loaded task, or an error listing the IDs held by
+    /// more than one task, since only one of them could be kept.
+    fn finish(self) -> Result<TaskRepository, TaskError> {
+        check_duplicate_ids(self.duplicates)?;
+        Ok(self.repo)
+    }
+}
+
+impl<'de> Deserialize<'de> for TaskLoader {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(TaskLoaderVisitor)
+    }
+}
+
+/// Reads an array of tasks into a `TaskLoader`, one task at a time.
+struct TaskLoaderVisitor;
+
+impl<'de> Visitor<'de> for TaskLoaderVisitor {
+    type Value = TaskLoader;
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("an array of tasks")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<TaskLoader, A::Error> {
+        let mut loader = TaskLoader::default();
+        while let Some(task) = seq.next_element()? {
+            loader.insert(task);
+        }
+        Ok(loader)
+    }
+}
+
+/// The shape of a `Wrapped` task file when its tasks are streamed into a `TaskLoader`.
+#[derive(Deserialize)]
+struct WrappedTaskLoader {
+    tasks: TaskLoader,
+}
+
+/// Fails if some IDs are held by more than one task.
+///
+/// # Arguments
+///
+/// * `duplicates` - The IDs read more than once, each listed once.
+///
+/// # Returns
+///
+/// An error listing the duplicated IDs in ascending order, if there are any.
+fn check_duplicate_ids(mut duplicates: Vec<i32>) -> Result<(), TaskError> {
+    if duplicates.is_empty() {
+        return Ok(());
+    }
+    duplicates.sort();
+    let ids: Vec<String> = duplicates.iter().map(ToString::to_string).collect();
+    Err(TaskError::Invalid(format!(
+        "Several tasks have the same id: {}",
+        ids.join(", ")
+    )))
+}
+
+/// The only field of a task read when the tasks are counted, the others being skipped over.
+#[derive(Deserialize)]
+struct TaskId {
+    id: i32,
+}
+
+/// Counts the tasks of a task file as they are read, noting the IDs read twice.
+#[derive(Default)]
+struct TaskCounter {
+    ids: HashSet<i32>,
+    duplicates: Vec<i32>,
+}
+
+impl TaskCounter {
+    /// Returns the number of tasks read.
+    ///
+    /// # Returns
+    ///
+    /// The number of tasks, or an error listing the IDs held by more than one task, since
+    /// loading the file would fail.
+    fn finish(self) -> Result<usize, TaskError> {
+        check_duplicate_ids(self.duplicates)?;
+        Ok(self.ids.len())
+    }
+}
+
+impl<'de> Deserialize<'de> for TaskCounter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(TaskCounterVisitor)
+    }
+}
+
+/// Reads the IDs of an array of tasks into a `TaskCounter`, one task at a time.
+struct TaskCounterVisitor;
+
+impl<'de> Visitor<'de> for TaskCounterVisitor {
+    type Value = TaskCounter;
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("an array of tasks")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<TaskCounter, A::Error> {
+        let mut counter = TaskCounter::default();
+        while let Some(TaskId { id }) = seq.next_element()? {
+            if !counter.ids.insert(id) && !counter.duplicates.contains(&id) {
+                counter.duplicates.push(id);
             }
-            task_repository.tasks.insert(task.id, task);
         }
-        task_repository.last_id = max_id;
-        task_repository
+        Ok(counter)
     }
+}
+
+/// The shape of a `Wrapped` task file when only its tasks are counted.
+#[derive(Deserialize)]
+struct TaskCountForSerialization {
+    tasks: TaskCounter,
+}
 
+impl TaskRepository {
     /// Adds a new task with the given description to the repository.
     ///
     /// # Arguments
     ///
     /// * `description` - A string describing the task.
     pub fn new_task(&mut self, description: String) {
-        self.last_id += 1;
+        self.new_task_at(description, Local::now());
+    }
+
+    /// Adds a new task with the given description and creation time to the repository.
+    ///
+    /// # Arguments
+    ///
+    /// * `description` - A string describing the task.
+    /// * `created_at` - The creation time of the task, also used as its last update time.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the new task.
+    pub fn new_task_at(&mut self, description: String, created_at: DateTime<Local>) -> i32 {
+        let id = if self.reuse_ids {
+            self.lowest_free_id()
+        } else {
+            self.last_id + 1
+        };
+        self.insert_new_task(id, description, created_at);
+        id
+    }
+
+    /// Returns the smallest positive ID not used by any task.
+    fn lowest_free_id(&self) -> i32 {
+        let mut id = 1;
+        for &used in self.tasks.keys().skip_while(|&&used| used < 1) {
+            if used != id {
+                break;
+            }
+            id += 1;
+        }
+        id
+    }
+
+    /// Makes new tasks take the lowest free ID, so that the IDs of deleted tasks are reused.
+    ///
+    /// # Arguments
+    ///
+    /// * `reuse_ids` - Whether to reuse freed IDs rather than always counting up from the highest ID.
+    pub fn set_reuse_ids(&mut self, reuse_ids: bool) {
+        self.reuse_ids = reuse_ids;
+    }
+
+    /// Adds a new task with the given ID and description to the repository.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the new task.
+    /// * `description` - A string describing the task.
+    ///
+    /// # Returns
+    ///
+    /// An error message if a task with this ID already exists.
+    pub fn new_task_with_id(&mut self, id: i32, description: String) -> Result<(), TaskError> {
+        self.new_task_with_id_at(id, description, Local::now())
+    }
+
+    /// Adds a new task with the given ID, description and creation time to the repository.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the new task.
+    /// * `description` - A string describing the task.
+    /// * `created_at` - The creation time of the task, also used as its last update time.
+    ///
+    /// # Returns
+    ///
+    /// An error message if a task with this ID already exists.
+    pub fn new_task_with_id_at(
+        &mut self,
+        id: i32,
+        description: String,
+        created_at: DateTime<Local>,
+    ) -> Result<(), TaskError> {
+        if self.tasks.contains_key(&id) {
+            return Err(TaskError::Invalid(format!(
+                "A task with id {} already exists",
+                id
+            )));
+        }
+        self.insert_new_task(id, description, created_at);
+        Ok(())
+    }
+
+    /// Inserts a new `Todo` task of `Medium` priority, keeping `last_id` at the highest ID in use.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the new task.
+    /// * `description` - A string describing the task.
+    /// * `created_at` - The creation time of the task, also used as its last update time.
+    fn insert_new_task(&mut self, id: i32, description: String, created_at: DateTime<Local>) {
         let task = Task {
             description,
-            id: self.last_id,
+            id,
             status: TaskStatus::Todo,
-            created_at: Local::now(),
-            updated_at: Local::now(),
+            priority: Priority::default(),
+            created_at,
+            updated_at: created_at,
+            due_date: None,
+            tags: Vec::new(),
+            completed_at: None,
+            checklist: Vec::new(),
         };
-        self.tasks.insert(self.last_id, task);
+        self.tasks.insert(id, task);
+        self.last_id = self.last_id.max(id);
+        self.search_index.0.take();
     }
 
     /// Converts the `TaskRepository` into a `TaskRepositoryForSerialization` object.
@@ -100,8 +570,7 @@ impl TaskRepository {
     ///
     /// A `TaskRepositoryForSerialization` object.
     fn serializable(&self) -> TaskRepositoryForSerialization {
-        let mut vec: Vec<Task> = self.tasks.values().cloned().collect();
-        vec.sort_by(|a, b| a.id.cmp(&b.id));
+        let vec: Vec<Task> = self.tasks.values().cloned().collect();
         TaskRepositoryForSerialization { tasks: vec }
     }
 
@@ -115,118 +584,2336 @@ impl TaskRepository {
     ///
     /// An `Option` containing the deleted task if it existed.
     pub fn delete(&mut self, id: i32) -> Option<Task> {
+        self.search_index.0.take();
         self.tasks.remove(&id)
     }
 
-    /// Returns an iterator over the tasks in the repository.
+    /// Keeps only the tasks satisfying the predicate and deletes the others.
+    ///
+    /// # Arguments
+    ///
+    /// * `pred` - Returns `true` for the tasks to keep.
     ///
     /// # Returns
     ///
-    /// An iterator over the tasks.
-    pub fn tasks(&self) -> Values<'_, i32, Task> {
-        self.tasks.values()
+    /// The number of deleted tasks.
+    pub fn retain<F: Fn(&Task) -> bool>(&mut self, pred: F) -> usize {
+        let count = self.tasks.len();
+        self.search_index.0.take();
+        self.tasks.retain(|_, task| pred(task));
+        count - self.tasks.len()
     }
 
-    /// Returns a mutable reference to the task with the given ID.
+    /// Deletes every `Done` task.
+    ///
+    /// # Returns
+    ///
+    /// The number of deleted tasks.
+    pub fn purge_done(&mut self) -> usize {
+        self.retain(|task| task.status != TaskStatus::Done)
+    }
+
+    /// Gives the tasks the IDs 1 to N in the order of their current IDs.
+    ///
+    /// `last_id` becomes the number of tasks. Nothing else about the tasks changes, not even
+    /// their update time.
+    ///
+    /// # Returns
+    ///
+    /// The number of tasks whose ID changed.
+    pub fn renumber(&mut self) -> usize {
+        let tasks = std::mem::take(&mut self.tasks);
+        self.search_index.0.take();
+        let mut changed = 0;
+        for (new_id, mut task) in (1..).zip(tasks.into_values()) {
+            if task.id != new_id {
+                task.id = new_id;
+                changed += 1;
+            }
+            self.tasks.insert(new_id, task);
+        }
+        self.last_id = self.tasks.len() as i32;
+        changed
+    }
+
+    /// Sets the description of the task with the given ID and refreshes its update time.
     ///
     /// # Arguments
     ///
-    /// * `id` - The ID of the task to retrieve.
+    /// * `id` - The ID of the task to update.
+    /// * `description` - The new description of the task.
     ///
     /// # Returns
     ///
-    /// A mutable reference to the task.
-    pub fn task(&mut self, id: i32) -> &mut Task {
-        self.tasks.get_mut(&id).unwrap()
+    /// An error message if the task does not exist.
+    pub fn set_description(&mut self, id: i32, description: String) -> Result<(), TaskError> {
+        let task = self.task(id).ok_or(TaskError::NotFound(id))?;
+        task.description = description;
+        task.touch_updated();
+        Ok(())
     }
 
-    /// Returns the number of tasks in the repository.
+    /// Sets the status of the task with the given ID and refreshes its update time.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the task to update.
+    /// * `status` - The new status of the task.
     ///
     /// # Returns
     ///
-    /// The number of tasks.
-    pub fn task_count(&self) -> usize {
-        self.tasks.len()
+    /// An error message if the task does not exist.
+    pub fn set_status(&mut self, id: i32, status: TaskStatus) -> Result<(), TaskError> {
+        let task = self.task(id).ok_or(TaskError::NotFound(id))?;
+        task.change_status(status);
+        Ok(())
     }
-}
 
-/// Load a `TaskRepository` from a JSON file at the provided path.
-///
-/// If the file does not exist, a default `TaskRepository` is returned.
-///
-/// # Arguments
-///
-/// * `file_path` - A reference to a path that implements the `AsRef<Path>` trait.
-///
-/// # Returns
-///
-/// A `TaskRepository` loaded from the JSON file.
-pub fn load_repository(file_path: &impl AsRef<Path>) -> TaskRepository {
-    if !fs::exists(file_path).unwrap() {
-        return TaskRepository::default();
+    /// Sets the priority of the task with the given ID and refreshes its update time.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the task to update.
+    /// * `priority` - The new priority of the task.
+    ///
+    /// # Returns
+    ///
+    /// An error message if the task does not exist.
+    pub fn set_priority(&mut self, id: i32, priority: Priority) -> Result<(), TaskError> {
+        let task = self.task(id).ok_or(TaskError::NotFound(id))?;
+        task.priority = priority;
+        task.touch_updated();
+        Ok(())
     }
-    let file = OpenOptions::new()
-        .read(true)
-        .create(true)
-        .truncate(false)
-        .write(true)
-        .open(file_path)
-        .unwrap();
-    let reader = BufReader::new(file);
-    let repo_object: TaskRepositoryForSerialization = serde_json::from_reader(reader).unwrap();
-    TaskRepository::from_serialization(repo_object)
-}
 
-/// Save a `TaskRepository` to a JSON file at the provided path.
-///
-/// # Arguments
-///
-/// * `repo` - A mutable reference to the `TaskRepository` to be saved.
-/// * `file_path` - A reference to a path that implements the `AsRef<Path>` trait.
-pub fn save_repository(repo: &mut TaskRepository, file_path: &impl AsRef<Path>) {
-    let mut list_file = fs::File::create(file_path).unwrap();
-    let _ = list_file.write(
-        serde_json::to_string(&repo.serializable())
-            .unwrap()
-            .as_bytes(),
-    );
-}
+    /// Sets or clears the due date of the task with the given ID and refreshes its update time.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the task to update.
+    /// * `due_date` - The new due date of the task, `None` to remove it.
+    ///
+    /// # Returns
+    ///
+    /// An error message if the task does not exist.
+    pub fn set_due_date(
+        &mut self,
+        id: i32,
+        due_date: Option<DateTime<Local>>,
+    ) -> Result<(), TaskError> {
+        let task = self.task(id).ok_or(TaskError::NotFound(id))?;
+        task.due_date = due_date;
+        task.touch_updated();
+        Ok(())
+    }
 
-#[cfg(test)]
-mod tests {
+    /// Adds a tag to the task with the given ID, doing nothing if it already carries it.
+    ///
+    /// The update time is refreshed only if the tag is added.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the task to update.
+    /// * `tag` - The tag to add.
+    ///
+    /// # Returns
+    ///
+    /// An error message if the task does not exist.
+    pub fn add_tag(&mut self, id: i32, tag: String) -> Result<(), TaskError> {
+        let tag = self.intern_tag(&tag);
+        let task = self.task(id).ok_or(TaskError::NotFound(id))?;
+        if !task.tags.contains(&tag) {
+            task.tags.push(tag);
+            task.touch_updated();
+        }
+        Ok(())
+    }
+
+    /// Returns the copy of a tag to give to the tasks of this repository.
+    ///
+    /// # Arguments
+    ///
+    /// * `tag` - The text of the tag.
+    ///
+    /// # Returns
+    ///
+    /// A `Tag` sharing its text with the tasks of the repository already carrying it.
+    pub fn intern_tag(&mut self, tag: &str) -> Tag {
+        self.tag_pool.intern(tag)
+    }
+
+    /// Appends an unchecked item to the checklist of the task with the given ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the task to update.
+    /// * `text` - The text of the item.
+    ///
+    /// # Returns
+    ///
+    /// The index of the new item, or an error message if the task does not exist.
+    pub fn add_checklist_item(&mut self, id: i32, text: String) -> Result<usize, TaskError> {
+        let task = self.task(id).ok_or(TaskError::NotFound(id))?;
+        task.checklist.push((text, false));
+        task.touch_updated();
+        Ok(task.checklist.len() - 1)
+    }
+
+    /// Checks or unchecks an item of the checklist of the task with the given ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the task to update.
+    /// * `index` - The index of the item, starting at 0.
+    ///
+    /// # Returns
+    ///
+    /// Whether the item is now checked, or an error message if the task or the item does not exist.
+    pub fn toggle_checklist_item(&mut self, id: i32, index: usize) -> Result<bool, TaskError> {
+        let task = self.task(id).ok_or(TaskError::NotFound(id))?;
+        let (_, checked) = task.checklist.get_mut(index).ok_or_else(|| {
+            TaskError::Invalid(format!("Task {} has no checklist item {}", id, index + 1))
+        })?;
+        *checked = !*checked;
+        let checked = *checked;
+        task.touch_updated();
+        Ok(checked)
+    }
+
+    /// Removes a tag from the task with the given ID, doing nothing if it does not carry it.
+    ///
+    /// The update time is refreshed only if the tag is removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the task to update.
+    /// * `tag` - The tag to remove.
+    ///
+    /// # Returns
+    ///
+    /// An error message if the task does not exist.
+    pub fn remove_tag(&mut self, id: i32, tag: &str) -> Result<(), TaskError> {
+        let task = self.task(id).ok_or(TaskError::NotFound(id))?;
+        let count = task.tags.len();
+        task.tags.retain(|task_tag| task_tag != tag);
+        if task.tags.len() != count {
+            task.touch_updated();
+        }
+        Ok(())
+    }
+
+    /// Moves a `Done` task back to `Todo`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the task to reopen.
+    ///
+    /// # Returns
+    ///
+    /// An error message if the task does not exist or is not `Done`.
+    pub fn reopen(&mut self, id: i32) -> Result<(), TaskError> {
+        let task = self.task(id).ok_or(TaskError::NotFound(id))?;
+        if task.status != TaskStatus::Done {
+            return Err(TaskError::Invalid(format!("Task {} is not done", id)));
+        }
+        task.change_status(TaskStatus::Todo);
+        Ok(())
+    }
+
+    /// Makes the task with the given ID due at the given time and moves it back to `Todo`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the task to bump.
+    /// * `due_date` - The new due date of the task.
+    ///
+    /// # Returns
+    ///
+    /// An error message if the task does not exist or is done.
+    pub fn bump(&mut self, id: i32, due_date: DateTime<Local>) -> Result<(), TaskError> {
+        let task = self.task(id).ok_or(TaskError::NotFound(id))?;
+        if task.status == TaskStatus::Done {
+            return Err(TaskError::Invalid(format!("Task {} is done", id)));
+        }
+        task.due_date = Some(due_date);
+        task.change_status(TaskStatus::Todo);
+        Ok(())
+    }
+
+    /// Returns the layout the repository is saved with.
+    pub fn layout(&self) -> FileLayout {
+        self.layout
+    }
+
+    /// Sets the layout the repository is saved with.
+    ///
+    /// # Arguments
+    ///
+    /// * `layout` - The new `FileLayout`.
+    pub fn set_layout(&mut self, layout: FileLayout) {
+        self.layout = layout;
+    }
+
+    /// Returns an iterator over the tasks in the repository, in ascending id order.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the tasks.
+    pub fn tasks(&self) -> Values<'_, i32, Task> {
+        self.tasks.values()
+    }
+
+    /// Returns a reference to the task with the given ID, for reading it only.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the task to retrieve.
+    ///
+    /// # Returns
+    ///
+    /// A reference to the task, or `None` if there is no task with this ID.
+    pub fn task_ref(&self, id: i32) -> Option<&Task> {
+        self.tasks.get(&id)
+    }
+
+    /// Returns the IDs of the existing tasks within a range of IDs.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - The inclusive range of IDs to look into.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the IDs in increasing order, which only visits existing tasks however
+    /// wide the range is.
+    pub fn ids_in_range(&self, ids: RangeInclusive<i32>) -> impl Iterator<Item = i32> + '_ {
+        self.tasks.range(ids).map(|(id, _)| *id)
+    }
+
+    /// Returns a mutable reference to the task with the given ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the task to retrieve.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the task, or `None` if there is no task with this ID.
+    /// The search index is dropped, since the description of the task may be changed.
+    pub fn task(&mut self, id: i32) -> Option<&mut Task> {
+        self.search_index.0.take();
+        self.tasks.get_mut(&id)
+    }
+
+    /// Returns the number of tasks in the repository.
+    ///
+    /// # Returns
+    ///
+    /// The number of tasks.
+    pub fn task_count(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Returns the `n` most recently updated tasks, newest first.
+    ///
+    /// If the repository holds fewer than `n` tasks, all of them are returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The maximum number of tasks to return.
+    ///
+    /// # Returns
+    ///
+    /// A vector of references to the most recently updated tasks.
+    pub fn most_recent(&self, n: usize) -> Vec<&Task> {
+        let mut tasks: Vec<&Task> = self.tasks.values().collect();
+        tasks.sort_by_key(|task| Reverse(task.updated_at));
+        tasks.truncate(n);
+        tasks
+    }
+
+    /// Returns the `n` most recently completed tasks, newest first.
+    ///
+    /// Only done tasks are returned, ordered by the time they were completed at.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The maximum number of tasks to return.
+    ///
+    /// # Returns
+    ///
+    /// A vector of references to the most recently completed tasks.
+    pub fn recently_completed(&self, n: usize) -> Vec<&Task> {
+        let mut tasks: Vec<&Task> = self
+            .tasks
+            .values()
+            .filter(|task| task.status == TaskStatus::Done)
+            .collect();
+        tasks.sort_by_key(|task| Reverse(task.completed_at));
+        tasks.truncate(n);
+        tasks
+    }
+
+    /// Returns the tasks with an id greater than the given one, in ascending id order.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Only tasks with an id strictly greater than this one are returned.
+    ///
+    /// # Returns
+    ///
+    /// A vector of references to the matching tasks.
+    pub fn tasks_after_id(&self, id: i32) -> Vec<&Task> {
+        self.tasks
+            .range(id.saturating_add(1)..)
+            .map(|(_, task)| task)
+            .collect()
+    }
+
+    /// Returns the tasks not done yet whose due date is before the given time, in ascending id order.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The time past which due tasks are overdue.
+    ///
+    /// # Returns
+    ///
+    /// A vector of references to the overdue tasks.
+    pub fn overdue(&self, now: DateTime<Local>) -> Vec<&Task> {
+        self.tasks
+            .values()
+            .filter(|task| task.is_overdue(now))
+            .collect()
+    }
+
+    /// Returns the tasks whose description contains the query, ignoring case, in ascending id order.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The text to look for in task descriptions.
+    ///
+    /// # Returns
+    ///
+    /// A vector of references to the matching tasks.
+    pub fn search(&self, query: &str) -> Vec<&Task> {
+        let query = query.to_lowercase();
+        let matches = |task: &&Task| task.description.to_lowercase().contains(&query);
+        // A word of the query, having no whitespace, can only be found within a single word
+        // of a matching description, so only the tasks with such a word are checked.
+        let Some(longest_word) = query.split_whitespace().max_by_key(|word| word.len()) else {
+            return self.tasks.values().filter(matches).collect();
+        };
+        let mut ids: Vec<i32> = self
+            .build_search_index()
+            .iter()
+            .filter(|(word, _)| word.contains(longest_word))
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect();
+        ids.sort();
+        ids.dedup();
+        ids.iter()
+            .filter_map(|id| self.tasks.get(id))
+            .filter(matches)
+            .collect()
+    }
+
+    /// Builds the index used by `search`, unless it is already built.
+    ///
+    /// `search` builds it on its first call, and any change to the tasks drops it.
+    ///
+    /// # Returns
+    ///
+    /// A map from each lowercased word of the task descriptions to the IDs of the tasks whose
+    /// description has it, in ascending order.
+    pub fn build_search_index(&self) -> &HashMap<String, Vec<i32>> {
+        self.search_index.0.get_or_init(|| {
+            let mut index: HashMap<String, Vec<i32>> = HashMap::new();
+            for task in self.tasks.values() {
+                for word in task.description.to_lowercase().split_whitespace() {
+                    let ids = index.entry(word.to_string()).or_default();
+                    if ids.last() != Some(&task.id) {
+                        ids.push(task.id);
+                    }
+                }
+            }
+            index
+        })
+    }
+
+    /// Returns the tasks having any of the given statuses, in ascending id order.
+    ///
+    /// # Arguments
+    ///
+    /// * `statuses` - The statuses to keep tasks of.
+    ///
+    /// # Returns
+    ///
+    /// A vector of references to the matching tasks.
+    pub fn tasks_with_statuses(&self, statuses: &[TaskStatus]) -> Vec<&Task> {
+        self.tasks
+            .values()
+            .filter(|task| statuses.contains(&task.status))
+            .collect()
+    }
+
+    /// Returns the number of tasks in the repository with the given status.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - The `TaskStatus` to count tasks for.
+    ///
+    /// # Returns
+    ///
+    /// The number of tasks with the given status.
+    pub fn count_by_status(&self, status: TaskStatus) -> usize {
+        self.tasks
+            .values()
+            .filter(|task| task.status == status)
+            .count()
+    }
+
+    /// Returns the number of tasks not done yet, i.e. todo or in progress.
+    pub fn active_count(&self) -> usize {
+        self.tasks
+            .values()
+            .filter(|task| task.status != TaskStatus::Done)
+            .count()
+    }
+
+    /// Returns the task to work on next among those not done yet.
+    ///
+    /// The task due the soonest comes first, tasks without a due date come after all those
+    /// with one. Ties are broken by the highest priority, then by the lowest id.
+    ///
+    /// # Returns
+    ///
+    /// The next task, or `None` if every task is done.
+    pub fn next_task(&self) -> Option<&Task> {
+        self.tasks
+            .values()
+            .filter(|task| task.status != TaskStatus::Done)
+            .min_by_key(|task| {
+                (
+                    task.due_date.is_none(),
+                    task.due_date,
+                    Reverse(task.priority),
+                    task.id,
+                )
+            })
+    }
+
+    /// Counts the tasks of the repository for each status.
+    ///
+    /// Named `status_counts` because `count_by_status` already counts a single status.
+    ///
+    /// # Returns
+    ///
+    /// A map from each status present in the repository to its number of tasks.
+    pub fn status_counts(&self) -> HashMap<TaskStatus, usize> {
+        let mut counts = HashMap::new();
+        for task in self.tasks.values() {
+            *counts.entry(task.status.clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Counts the tasks of the repository for each priority.
+    ///
+    /// Every task has a priority, those of files written before priorities existed being read
+    /// as `Medium`, so there is no count for tasks without one.
+    ///
+    /// # Returns
+    ///
+    /// A map from each priority present in the repository to its number of tasks.
+    pub fn priority_counts(&self) -> BTreeMap<Priority, usize> {
+        let mut counts = BTreeMap::new();
+        for task in self.tasks.values() {
+            *counts.entry(task.priority).or_insert(0) += 1;
+        }
+        counts
+    }
+}
+
+/// Describes the format of the task file as a JSON Schema.
+///
+/// The schema is written by hand and must be kept in sync with `Task` and
+/// `TaskRepositoryForSerialization`. Fields with a serde default are not required.
+///
+/// # Returns
+///
+/// The JSON Schema of the task file.
+pub fn json_schema() -> serde_json::Value {
+    serde_json::json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Task file",
+        "type": "object",
+        "required": ["tasks"],
+        "properties": {
+            "tasks": {
+                "type": "array",
+                "items": { "$ref": "#/$defs/task" }
+            }
+        },
+        "$defs": {
+            "task": {
+                "type": "object",
+                "required": ["id", "description", "status", "created_at", "updated_at"],
+                "properties": {
+                    "id": { "type": "integer" },
+                    "description": { "type": "string" },
+                    "status": { "enum": ["todo", "in-progress", "done"] },
+                    "priority": { "enum": ["Low", "Medium", "High"], "default": "Medium" },
+                    "created_at": { "type": "string", "format": "date-time" },
+                    "updated_at": { "type": "string", "format": "date-time" },
+                    "due_date": {
+                        "type": ["string", "null"],
+                        "format": "date-time",
+                        "default": null
+                    },
+                    "completed_at": {
+                        "type": ["string", "null"],
+                        "format": "date-time",
+                        "default": null
+                    },
+                    "tags": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "default": []
+                    },
+                    "checklist": {
+                        "type": "array",
+                        "items": {
+                            "type": "array",
+                            "prefixItems": [{ "type": "string" }, { "type": "boolean" }],
+                            "minItems": 2,
+                            "maxItems": 2
+                        },
+                        "default": []
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Load a `TaskRepository` from a JSON file at the provided path.
+///
+/// If the file does not exist, a default `TaskRepository` is returned.
+/// Both layouts are accepted: a top-level array is read as `FileLayout::Array`, an object
+/// as `FileLayout::Wrapped`, and the repository remembers which one it was.
+///
+/// # Arguments
+///
+/// * `file_path` - A reference to a path that implements the `AsRef<Path>` trait.
+///
+/// # Returns
+///
+/// A `TaskRepository` loaded from the JSON file, or an error message if the file
+/// cannot be read or does not hold a valid task list.
+pub fn load_repository(file_path: &impl AsRef<Path>) -> Result<TaskRepository, TaskError> {
+    load_repository_with_version(file_path).map(|(repo, _)| repo)
+}
+
+/// Loads a `TaskRepository` like `load_repository`, along with the version of the file.
+///
+/// # Arguments
+///
+/// * `file_path` - A reference to a path that implements the `AsRef<Path>` trait.
+///
+/// # Returns
+///
+/// The loaded `TaskRepository` and the `FileVersion` of the content it was loaded from, or
+/// an error message if the file cannot be read or does not hold a valid task list.
+pub fn load_repository_with_version(
+    file_path: &impl AsRef<Path>,
+) -> Result<(TaskRepository, FileVersion), TaskError> {
+    let path = file_path.as_ref();
+    let read_error = |err| {
+        TaskError::io(
+            format_args!("Failed to read task file {}", path.display()),
+            err,
+        )
+    };
+    if !fs::exists(path).map_err(read_error)? {
+        return Ok((TaskRepository::default(), FileVersion::default()));
+    }
+    let file = OpenOptions::new()
+        .read(true)
+        .open(path)
+        .map_err(read_error)?;
+    let mut reader = BufReader::new(HashingReader {
+        inner: file,
+        hasher: ContentHasher::new(),
+    });
+    let (loader, layout) = if peek_first_byte(&mut reader).map_err(read_error)? == Some(b'[') {
+        (serde_json::from_reader(&mut reader)?, FileLayout::Array)
+    } else {
+        let wrapped: WrappedTaskLoader = serde_json::from_reader(&mut reader)?;
+        (wrapped.tasks, FileLayout::Wrapped)
+    };
+    let mut repo = loader.finish()?;
+    repo.layout = layout;
+    // The JSON was read up to the end of the file, so every byte went through the hasher.
+    Ok((repo, reader.into_inner().hasher.version()))
+}
+
+/// Skips the whitespace at the start of a reader and returns the next byte, left unread.
+///
+/// # Arguments
+///
+/// * `reader` - The reader to peek into.
+///
+/// # Returns
+///
+/// The first byte that is not whitespace, or `None` if there is none.
+fn peek_first_byte(reader: &mut impl BufRead) -> std::io::Result<Option<u8>> {
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(None);
+        }
+        let blank = buffer
+            .iter()
+            .take_while(|byte| byte.is_ascii_whitespace())
+            .count();
+        let next = buffer.get(blank).copied();
+        reader.consume(blank);
+        if next.is_some() {
+            return Ok(next);
+        }
+    }
+}
+
+/// Counts the tasks of a JSON task file without building them.
+///
+/// Only the ID of each task is read, the rest being skipped over, so the file must be valid
+/// JSON in either layout and its IDs must be unique as for `load_repository`, but the other
+/// fields of its tasks are not checked.
+///
+/// # Arguments
+///
+/// * `file_path` - A reference to a path that implements the `AsRef<Path>` trait.
+///
+/// # Returns
+///
+/// The number of tasks in the file, 0 if it does not exist, or an error message if the
+/// file cannot be read or is not a task list.
+pub fn count_tasks(file_path: &impl AsRef<Path>) -> Result<usize, TaskError> {
+    let path = file_path.as_ref();
+    let read_error = |err| {
+        TaskError::io(
+            format_args!("Failed to read task file {}", path.display()),
+            err,
+        )
+    };
+    if !fs::exists(path).map_err(read_error)? {
+        return Ok(0);
+    }
+    // No task is built, so the file is all there is to hold in memory, and parsing it from a
+    // slice is much faster than from a reader.
+    let content = fs::read(path).map_err(read_error)?;
+    let mut content = content.as_slice();
+    let is_array = peek_first_byte(&mut content).map_err(read_error)? == Some(b'[');
+    let counter: TaskCounter = if is_array {
+        serde_json::from_slice(content)?
+    } else {
+        serde_json::from_slice::<TaskCountForSerialization>(content)?.tasks
+    };
+    counter.finish()
+}
+
+/// Save a `TaskRepository` to a JSON file at the provided path, in the repository's layout.
+///
+/// The file is replaced atomically, so a failure while saving leaves the previous content intact.
+/// That previous content is first copied to the backup file given by `backup_path`, from which
+/// `restore_backup` can bring it back. If the file already holds the content to save, nothing
+/// is written, so that the backup still holds the content from before the last real change.
+///
+/// # Arguments
+///
+/// * `repo` - A mutable reference to the `TaskRepository` to be saved.
+/// * `file_path` - A reference to a path that implements the `AsRef<Path>` trait.
+///
+/// # Returns
+///
+/// The `FileVersion` of the written content, or an error if the file was not fully written.
+pub fn save_repository(
+    repo: &mut TaskRepository,
+    file_path: &impl AsRef<Path>,
+) -> std::io::Result<FileVersion> {
+    let object = repo.serializable();
+    let content = match repo.layout {
+        FileLayout::Wrapped => serde_json::to_string_pretty(&object)?,
+        FileLayout::Array => serde_json::to_string_pretty(&object.tasks)?,
+    };
+    let path = file_path.as_ref();
+    let mut hasher = ContentHasher::new();
+    hasher.update(content.as_bytes());
+    if fs::exists(path)? {
+        let previous_content = fs::read(path)?;
+        if previous_content == content.as_bytes() {
+            return Ok(hasher.version());
+        }
+        write_atomically(&backup_path(path), |file| file.write_all(&previous_content))?;
+    }
+    write_atomically(path, |file| file.write_all(content.as_bytes()))?;
+    Ok(hasher.version())
+}
+
+/// Returns the path of the file holding the content a task file had before its last save.
+///
+/// # Arguments
+///
+/// * `file_path` - The path of the task file.
+///
+/// # Returns
+///
+/// The path of the task file with `.bak` appended.
+pub fn backup_path(file_path: &Path) -> PathBuf {
+    let mut path = file_path.as_os_str().to_owned();
+    path.push(".bak");
+    PathBuf::from(path)
+}
+
+/// Replaces the tasks of a repository with those of the backup of its task file.
+///
+/// The repository keeps its own settings, such as its layout. Nothing is saved, saving the
+/// restored repository makes the state it replaced the new backup.
+///
+/// # Arguments
+///
+/// * `repo` - A mutable reference to the `TaskRepository` to restore.
+/// * `file_path` - The path of the task file whose backup is restored.
+///
+/// # Returns
+///
+/// An error message if there is no backup or it cannot be loaded.
+pub fn restore_backup(
+    repo: &mut TaskRepository,
+    file_path: &impl AsRef<Path>,
+) -> Result<(), TaskError> {
+    let path = backup_path(file_path.as_ref());
+    if !fs::exists(&path).unwrap_or(false) {
+        return Err(TaskError::Invalid("Nothing to undo".to_string()));
+    }
+    let backup = load_repository(&path)?;
+    replace_tasks(repo, backup);
+    Ok(())
+}
+
+/// Writes every task of a repository as pretty JSON, for `restore_dump` to read back.
+///
+/// The dump is always in the `Wrapped` layout, whatever the layout of the repository.
+///
+/// # Arguments
+///
+/// * `repo` - A reference to the `TaskRepository` to dump.
+/// * `writer` - The writer receiving the JSON.
+///
+/// # Returns
+///
+/// A `serde_json::Result` indicating whether the dump was fully written.
+pub fn dump_repository(repo: &TaskRepository, writer: impl Write) -> serde_json::Result<()> {
+    serde_json::to_writer_pretty(writer, &repo.serializable())
+}
+
+/// Replaces the tasks of a repository with those of a dump, or of any task file.
+///
+/// The dump is fully parsed first, so the repository is left untouched if it is malformed.
+/// As for `restore_backup`, the repository keeps its own settings and nothing is saved.
+///
+/// # Arguments
+///
+/// * `repo` - A mutable reference to the `TaskRepository` to restore.
+/// * `file_path` - The path of the dump.
+///
+/// # Returns
+///
+/// The number of restored tasks, or an error message if the dump is missing or invalid.
+pub fn restore_dump(
+    repo: &mut TaskRepository,
+    file_path: &impl AsRef<Path>,
+) -> Result<usize, TaskError> {
+    let path = file_path.as_ref();
+    if !fs::exists(path).unwrap_or(false) {
+        return Err(TaskError::Invalid(format!(
+            "Dump file {} does not exist",
+            path.display()
+        )));
+    }
+    let dump = load_repository(&path)?;
+    let count = dump.task_count();
+    replace_tasks(repo, dump);
+    Ok(count)
+}
+
+/// Moves the tasks and the last used ID of another repository into this one.
+///
+/// # Arguments
+///
+/// * `repo` - A mutable reference to the `TaskRepository` receiving the tasks.
+/// * `other` - The `TaskRepository` giving its tasks.
+fn replace_tasks(repo: &mut TaskRepository, other: TaskRepository) {
+    repo.tasks = other.tasks;
+    repo.last_id = other.last_id;
+    repo.tag_pool = other.tag_pool;
+    repo.search_index.0.take();
+}
+
+/// An advisory lock on a task file, held as long as this value lives.
+///
+/// The lock is taken by the operating system on a sibling file whose name is the task file's
+/// with `.lock` appended. It is released when this value is dropped or when the process ends,
+/// however it ends, so a lock file left behind does not keep the task file locked.
+#[derive(Debug)]
+pub struct FileLock {
+    _file: fs::File,
+}
+
+/// Takes the lock on a task file, to be held while it is checked and saved.
+///
+/// Taking the lock does not wait: if another process holds it, an error is returned at once.
+///
+/// # Arguments
+///
+/// * `file_path` - The path of the task file to lock.
+///
+/// # Returns
+///
+/// The `FileLock`, or an error message if the file is already locked or the lock file cannot
+/// be created.
+pub fn lock_file(file_path: &impl AsRef<Path>) -> Result<FileLock, TaskError> {
+    let path = file_path.as_ref();
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let lock_path = PathBuf::from(lock_path);
+    let lock_error = |err| {
+        TaskError::io(
+            format_args!("Failed to lock task file {}", path.display()),
+            err,
+        )
+    };
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .map_err(lock_error)?;
+    match file.try_lock() {
+        Ok(()) => Ok(FileLock { _file: file }),
+        Err(fs::TryLockError::WouldBlock) => Err(TaskError::Invalid(format!(
+            "Task file {} is locked by another process",
+            path.display()
+        ))),
+        Err(fs::TryLockError::Error(err)) => Err(lock_error(err)),
+    }
+}
+
+/// What a task file held when it was read or written, to tell whether it changed since.
+///
+/// The default version is that of a missing file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FileVersion(Option<u64>);
+
+/// Hashes the content of a file as it goes by, with 64-bit FNV-1a.
+///
+/// The hash only depends on the bytes, not on how they are split between calls to `update`.
+struct ContentHasher(u64);
+
+impl ContentHasher {
+    fn new() -> Self {
+        ContentHasher(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn version(&self) -> FileVersion {
+        FileVersion(Some(self.0))
+    }
+}
+
+/// A reader hashing every byte read through it.
+struct HashingReader<R> {
+    inner: R,
+    hasher: ContentHasher,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.hasher.update(&buf[..count]);
+        Ok(count)
+    }
+}
+
+/// Returns the version of the task file at the given path.
+///
+/// # Arguments
+///
+/// * `file_path` - A reference to a path that implements the `AsRef<Path>` trait.
+///
+/// # Returns
+///
+/// The `FileVersion` of the file, or an error message if it cannot be read.
+pub fn file_version(file_path: &impl AsRef<Path>) -> Result<FileVersion, TaskError> {
+    let path = file_path.as_ref();
+    let read_error = |err| {
+        TaskError::io(
+            format_args!("Failed to read task file {}", path.display()),
+            err,
+        )
+    };
+    if !fs::exists(path).map_err(read_error)? {
+        return Ok(FileVersion::default());
+    }
+    let mut reader = HashingReader {
+        inner: fs::File::open(path).map_err(read_error)?,
+        hasher: ContentHasher::new(),
+    };
+    std::io::copy(&mut reader, &mut std::io::sink()).map_err(read_error)?;
+    Ok(reader.hasher.version())
+}
+
+/// Replaces the content of a file atomically.
+///
+/// The content is written to a temporary file in the same directory, which is then renamed
+/// over the destination. If the destination is a symlink, the file it points to is replaced
+/// and the link is kept. The file keeps its permissions, or gets the default ones of a new
+/// file if it did not exist, rather than the private ones of a temporary file.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file to replace.
+/// * `write` - A function writing the new content to the temporary file.
+///
+/// # Returns
+///
+/// An `io::Result` indicating whether the file was replaced.
+fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut fs::File) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let path = resolve_symlinks(path)?;
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut builder = Builder::new();
+    // Masked by the umask when the file is created, like with `fs::File::create`.
+    #[cfg(unix)]
+    builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o666));
+    let mut tmp_file = builder.tempfile_in(dir)?;
+    if let Ok(metadata) = fs::metadata(&path) {
+        tmp_file.as_file().set_permissions(metadata.permissions())?;
+    }
+    write(tmp_file.as_file_mut())?;
+    tmp_file.as_file().sync_all()?;
+    tmp_file.persist(&path).map_err(|err| err.error)?;
+    Ok(())
+}
+
+/// The number of symlinks `resolve_symlinks` follows before giving up, as Linux does.
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// Follows symlinks until reaching a path that is not one.
+///
+/// The final target does not need to exist.
+///
+/// # Arguments
+///
+/// * `path` - The path to resolve.
+///
+/// # Returns
+///
+/// The resolved path, or an error if it cannot be read or if more than `MAX_SYMLINK_HOPS`
+/// symlinks follow one another, as they do when they form a loop.
+fn resolve_symlinks(path: &Path) -> std::io::Result<PathBuf> {
+    let original = path;
+    let mut path = path.to_path_buf();
+    let mut hops = 0;
+    while fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.is_symlink()) {
+        if hops == MAX_SYMLINK_HOPS {
+            return Err(std::io::Error::other(format!(
+                "Too many levels of symbolic links from {}",
+                original.display()
+            )));
+        }
+        hops += 1;
+        let target = fs::read_link(&path)?;
+        path = match path.parent() {
+            Some(parent) => parent.join(target),
+            None => target,
+        };
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
     use super::*;
-    use chrono::TimeZone;
+    use chrono::{TimeDelta, TimeZone};
     use serde_json::Value;
+    use std::env;
+
+    /// Builds a repository from tasks all deserialized beforehand, the way task files were
+    /// loaded before `load_repository` streamed them.
+    fn from_serialization(
+        object: TaskRepositoryForSerialization,
+    ) -> Result<TaskRepository, TaskError> {
+        let mut loader = TaskLoader::default();
+        for task in object.tasks {
+            loader.insert(task);
+        }
+        loader.finish()
+    }
+
+    #[test]
+    fn repository_save_json() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.new_task(String::from("plap"));
+        repo.task(1).unwrap().status = TaskStatus::Done;
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        save_repository(&mut repo, &path).unwrap();
+        let serialized_data = fs::read_to_string(&path).unwrap();
+        let json_object: Value = serde_json::from_str(&serialized_data).unwrap();
+
+        assert!(serialized_data.contains("\n  \"tasks\": [\n"));
+        assert!(serialized_data.contains("\n      \"description\": \"plop\",\n"));
+        assert_eq!(json_object["tasks"][0]["description"], "plop");
+        assert_eq!(json_object["tasks"][0]["status"], "done");
+        assert_eq!(json_object["tasks"][1]["description"], "plap");
+        assert_eq!(load_repository(&path).unwrap(), repo);
+    }
+
+    #[test]
+    fn status_accepts_all_spellings() {
+        for (spelling, status) in [
+            ("Todo", TaskStatus::Todo),
+            ("todo", TaskStatus::Todo),
+            ("InProgress", TaskStatus::InProgress),
+            ("in-progress", TaskStatus::InProgress),
+            ("in_progress", TaskStatus::InProgress),
+            ("Done", TaskStatus::Done),
+            ("done", TaskStatus::Done),
+        ] {
+            let parsed: TaskStatus = serde_json::from_str(&format!("\"{}\"", spelling)).unwrap();
+            assert_eq!(parsed, status);
+        }
+        assert!(serde_json::from_str::<TaskStatus>("\"Started\"").is_err());
+    }
+
+    #[test]
+    fn status_is_saved_in_canonical_form() {
+        let statuses = [TaskStatus::Todo, TaskStatus::InProgress, TaskStatus::Done];
+        assert_eq!(
+            serde_json::to_string(&statuses).unwrap(),
+            r#"["todo","in-progress","done"]"#
+        );
+    }
+
+    #[test]
+    fn count_by_status_on_mixed_repo() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.new_task(String::from("plap"));
+        repo.new_task(String::from("plip"));
+        repo.new_task(String::from("plup"));
+        repo.task(1).unwrap().status = TaskStatus::Done;
+        repo.task(2).unwrap().status = TaskStatus::InProgress;
+        repo.task(3).unwrap().status = TaskStatus::Done;
+
+        assert_eq!(repo.count_by_status(TaskStatus::Todo), 1);
+        assert_eq!(repo.count_by_status(TaskStatus::InProgress), 1);
+        assert_eq!(repo.count_by_status(TaskStatus::Done), 2);
+    }
+
+    #[test]
+    fn status_counts_on_mixed_repo() {
+        let mut repo = TaskRepository::default();
+        for desc in ["plop", "plap", "plip", "plup"] {
+            repo.new_task(String::from(desc));
+        }
+        repo.set_status(1, TaskStatus::Done).unwrap();
+        repo.set_status(2, TaskStatus::Done).unwrap();
+        repo.set_status(3, TaskStatus::InProgress).unwrap();
+
+        let counts = repo.status_counts();
+        assert_eq!(counts[&TaskStatus::Done], 2);
+        assert_eq!(counts[&TaskStatus::InProgress], 1);
+        assert_eq!(counts[&TaskStatus::Todo], 1);
+        assert!(TaskRepository::default().status_counts().is_empty());
+    }
+
+    #[test]
+    fn priority_counts_on_mixed_repo() {
+        let mut repo = TaskRepository::default();
+        for desc in ["plop", "plap", "plip", "plup"] {
+            repo.new_task(String::from(desc));
+        }
+        repo.set_priority(1, Priority::High).unwrap();
+        repo.set_priority(2, Priority::High).unwrap();
+        repo.set_priority(3, Priority::Low).unwrap();
+
+        let counts = repo.priority_counts();
+        assert_eq!(counts[&Priority::High], 2);
+        assert_eq!(counts[&Priority::Medium], 1);
+        assert_eq!(counts[&Priority::Low], 1);
+        assert!(TaskRepository::default().priority_counts().is_empty());
+    }
+
+    #[test]
+    fn active_count_excludes_done_tasks() {
+        let mut repo = TaskRepository::default();
+        for desc in ["plop", "plap", "plip"] {
+            repo.new_task(String::from(desc));
+        }
+        repo.set_status(1, TaskStatus::Done).unwrap();
+        repo.set_status(2, TaskStatus::InProgress).unwrap();
+        assert_eq!(repo.active_count(), 2);
+        assert_eq!(TaskRepository::default().active_count(), 0);
+    }
+
+    #[test]
+    fn count_by_status_on_empty_repo() {
+        let repo = TaskRepository::default();
+        assert_eq!(repo.count_by_status(TaskStatus::Todo), 0);
+        assert_eq!(repo.count_by_status(TaskStatus::InProgress), 0);
+        assert_eq!(repo.count_by_status(TaskStatus::Done), 0);
+    }
+
+    #[test]
+    fn next_task_is_soonest_due() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.new_task(String::from("plap"));
+        repo.new_task(String::from("plip"));
+        let soon = Local.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap();
+        let later = Local.with_ymd_and_hms(2024, 7, 1, 10, 0, 0).unwrap();
+        repo.set_priority(1, Priority::High).unwrap();
+        repo.set_due_date(2, Some(later)).unwrap();
+        repo.set_due_date(3, Some(soon)).unwrap();
+        assert_eq!(repo.next_task().unwrap().id, 3);
+
+        repo.set_status(3, TaskStatus::Done).unwrap();
+        assert_eq!(repo.next_task().unwrap().id, 2);
+    }
+
+    #[test]
+    fn next_task_without_due_dates_is_highest_priority() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.new_task(String::from("plap"));
+        repo.new_task(String::from("plip"));
+        repo.set_priority(1, Priority::Low).unwrap();
+        repo.set_priority(3, Priority::High).unwrap();
+        assert_eq!(repo.next_task().unwrap().id, 3);
+
+        repo.set_priority(3, Priority::Medium).unwrap();
+        assert_eq!(repo.next_task().unwrap().id, 2);
+    }
+
+    #[test]
+    fn next_task_breaks_due_date_ties_by_priority() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.new_task(String::from("plap"));
+        let due_date = Local.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap();
+        repo.set_due_date(1, Some(due_date)).unwrap();
+        repo.set_due_date(2, Some(due_date)).unwrap();
+        repo.set_priority(2, Priority::High).unwrap();
+        assert_eq!(repo.next_task().unwrap().id, 2);
+    }
+
+    #[test]
+    fn next_task_ignores_done_tasks() {
+        let mut repo = TaskRepository::default();
+        assert_eq!(repo.next_task(), None);
+        repo.new_task(String::from("plop"));
+        repo.set_status(1, TaskStatus::Done).unwrap();
+        assert_eq!(repo.next_task(), None);
+    }
+
+    #[test]
+    fn most_recent_orders_newest_first_and_truncates() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.new_task(String::from("plap"));
+        repo.new_task(String::from("plip"));
+        repo.task(1).unwrap().updated_at = Local.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap();
+        repo.task(2).unwrap().updated_at = Local.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap();
+        repo.task(3).unwrap().updated_at = Local.with_ymd_and_hms(2024, 7, 1, 10, 0, 0).unwrap();
+
+        let ids: Vec<i32> = repo.most_recent(2).iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![3, 1]);
+    }
+
+    #[test]
+    fn most_recent_returns_all_when_fewer_than_n() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.new_task(String::from("plap"));
+        repo.task(1).unwrap().updated_at = Local.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap();
+        repo.task(2).unwrap().updated_at = Local.with_ymd_and_hms(2024, 6, 1, 10, 0, 0).unwrap();
+
+        let ids: Vec<i32> = repo.most_recent(5).iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![2, 1]);
+    }
+
+    #[test]
+    fn recently_completed_orders_done_tasks_newest_first() {
+        let mut repo = TaskRepository::default();
+        for desc in ["plop", "plap", "plip", "plup"] {
+            repo.new_task(desc.to_string());
+        }
+        for id in [1, 2, 4] {
+            repo.set_status(id, TaskStatus::Done).unwrap();
+        }
+        repo.task(1).unwrap().completed_at =
+            Some(Local.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap());
+        repo.task(2).unwrap().completed_at =
+            Some(Local.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap());
+        repo.task(3).unwrap().updated_at = Local.with_ymd_and_hms(2024, 9, 1, 10, 0, 0).unwrap();
+        repo.task(4).unwrap().completed_at =
+            Some(Local.with_ymd_and_hms(2024, 7, 1, 10, 0, 0).unwrap());
+
+        let ids: Vec<i32> = repo
+            .recently_completed(5)
+            .iter()
+            .map(|task| task.id)
+            .collect();
+        assert_eq!(ids, vec![4, 1, 2]);
+        let ids: Vec<i32> = repo
+            .recently_completed(2)
+            .iter()
+            .map(|task| task.id)
+            .collect();
+        assert_eq!(ids, vec![4, 1]);
+    }
+
+    #[test]
+    fn tasks_iterate_in_ascending_id_order() {
+        for _ in 0..10 {
+            let mut repo = TaskRepository::default();
+            for id in [7, 2, 9, 4, 1, 8] {
+                repo.new_task_with_id(id, format!("task {}", id)).unwrap();
+            }
+            repo.new_task(String::from("last"));
+            let ids: Vec<i32> = repo.tasks().map(|task| task.id).collect();
+            assert_eq!(ids, vec![1, 2, 4, 7, 8, 9, 10]);
+        }
+    }
+
+    #[test]
+    fn tasks_after_id_returns_only_higher_ids() {
+        let mut repo = TaskRepository::default();
+        for desc in ["plop", "plap", "plip", "plup"] {
+            repo.new_task(String::from(desc));
+        }
+        repo.delete(3);
+
+        let ids: Vec<i32> = repo.tasks_after_id(1).iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![2, 4]);
+        assert!(repo.tasks_after_id(4).is_empty());
+    }
+
+    #[test]
+    fn tasks_with_statuses_keeps_any_listed_status() {
+        let mut repo = TaskRepository::default();
+        for desc in ["plop", "plap", "plip", "plup"] {
+            repo.new_task(String::from(desc));
+        }
+        repo.task(1).unwrap().status = TaskStatus::Done;
+        repo.task(3).unwrap().status = TaskStatus::InProgress;
+
+        let statuses = [TaskStatus::Todo, TaskStatus::InProgress];
+        let ids: Vec<i32> = repo
+            .tasks_with_statuses(&statuses)
+            .iter()
+            .map(|task| task.id)
+            .collect();
+        assert_eq!(ids, vec![2, 3, 4]);
+        assert!(repo.tasks_with_statuses(&[]).is_empty());
+    }
+
+    #[test]
+    fn status_from_str() {
+        assert_eq!("WIP".parse::<TaskStatus>(), Ok(TaskStatus::InProgress));
+        assert_eq!("Done".parse::<TaskStatus>(), Ok(TaskStatus::Done));
+        assert_eq!(
+            "doing".parse::<TaskStatus>(),
+            Err(TaskError::Invalid("Unknown status: doing".to_string()))
+        );
+    }
+
+    #[test]
+    fn search_ignores_case() {
+        let mut repo = TaskRepository::default();
+        for desc in ["Buy milk", "Call the bank", "buy BREAD"] {
+            repo.new_task(String::from(desc));
+        }
+
+        let ids: Vec<i32> = repo.search("BUY").iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![1, 3]);
+        assert!(repo.search("cheese").is_empty());
+    }
+
+    #[test]
+    fn indexed_search_matches_linear_search() {
+        let mut repo = TaskRepository::default();
+        for desc in [
+            "Buy milk",
+            "Call the bank about the loan",
+            "buy BREAD and milk",
+            "Ébauche du plan",
+            "milkshake   party",
+        ] {
+            repo.new_task(String::from(desc));
+        }
+        repo.delete(2);
+        let linear_search = |repo: &TaskRepository, query: &str| -> Vec<i32> {
+            let query = query.to_lowercase();
+            repo.tasks()
+                .filter(|task| task.description.to_lowercase().contains(&query))
+                .map(|task| task.id)
+                .collect()
+        };
+        for query in [
+            "milk",
+            "MILK",
+            "buy",
+            "y mi",
+            "bread and",
+            "ke   pa",
+            "ébau",
+            "",
+            " ",
+            "bank",
+            "nothing",
+        ] {
+            let ids: Vec<i32> = repo.search(query).iter().map(|task| task.id).collect();
+            assert_eq!(ids, linear_search(&repo, query), "{:?}", query);
+        }
+        assert_eq!(repo.build_search_index().get("milk"), Some(&vec![1, 3]));
+    }
+
+    #[test]
+    fn search_index_is_dropped_on_change() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("Buy milk"));
+        assert_eq!(repo.search("milk").len(), 1);
+
+        repo.set_description(1, String::from("Buy bread")).unwrap();
+        assert!(repo.search("milk").is_empty());
+        repo.new_task(String::from("More milk"));
+        let ids: Vec<i32> = repo.search("milk").iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![2]);
+        repo.delete(2);
+        assert!(repo.search("milk").is_empty());
+        repo.renumber();
+        assert_eq!(repo.search("bread").len(), 1);
+    }
+
+    #[test]
+    fn retain_by_status() {
+        let mut repo = TaskRepository::default();
+        for desc in ["plop", "plap", "plip"] {
+            repo.new_task(String::from(desc));
+        }
+        repo.set_status(1, TaskStatus::Done).unwrap();
+        repo.set_status(3, TaskStatus::Done).unwrap();
+
+        assert_eq!(repo.retain(|task| task.status != TaskStatus::Done), 2);
+        assert_eq!(repo.task_count(), 1);
+        assert!(repo.task(2).is_some());
+        assert_eq!(repo.retain(|task| task.status != TaskStatus::Done), 0);
+    }
+
+    #[test]
+    fn retain_by_age() {
+        let now = Local::now();
+        let mut repo = TaskRepository::default();
+        repo.new_task_at(String::from("recent"), now - TimeDelta::days(1));
+        repo.new_task_at(String::from("old"), now - TimeDelta::days(100));
+
+        assert_eq!(
+            repo.retain(|task| now - task.created_at <= TimeDelta::days(30)),
+            1
+        );
+        assert_eq!(repo.task(1).unwrap().description, "recent");
+        assert!(repo.task(2).is_none());
+    }
+
+    #[test]
+    fn add_tag_ignores_duplicates() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.add_tag(1, String::from("work")).unwrap();
+        let updated_at = repo.task(1).unwrap().updated_at;
+        repo.add_tag(1, String::from("work")).unwrap();
+
+        let task = repo.task(1).unwrap();
+        assert_eq!(task.tags, vec!["work"]);
+        assert_eq!(task.updated_at, updated_at);
+        assert!(repo.add_tag(2, String::from("work")).is_err());
+    }
+
+    #[test]
+    fn add_and_toggle_checklist_items() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        assert_eq!(repo.add_checklist_item(1, String::from("first")), Ok(0));
+        assert_eq!(repo.add_checklist_item(1, String::from("second")), Ok(1));
+
+        assert_eq!(repo.toggle_checklist_item(1, 1), Ok(true));
+        assert_eq!(
+            repo.task(1).unwrap().checklist,
+            vec![
+                (String::from("first"), false),
+                (String::from("second"), true)
+            ]
+        );
+        assert_eq!(repo.toggle_checklist_item(1, 1), Ok(false));
+        assert_eq!(
+            repo.toggle_checklist_item(1, 2),
+            Err(TaskError::Invalid(String::from(
+                "Task 1 has no checklist item 3"
+            )))
+        );
+        assert_eq!(
+            repo.add_checklist_item(2, String::from("first")),
+            Err(TaskError::NotFound(2))
+        );
+    }
+
+    #[test]
+    fn checklist_round_trip() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.new_task(String::from("plap"));
+        repo.add_checklist_item(1, String::from("first")).unwrap();
+        repo.add_checklist_item(1, String::from("second")).unwrap();
+        repo.toggle_checklist_item(1, 0).unwrap();
+        save_repository(&mut repo, &path).unwrap();
+
+        let mut loaded_repo = load_repository(&path).unwrap();
+        assert_eq!(loaded_repo, repo);
+        assert_eq!(loaded_repo.task(1).unwrap().checklist.len(), 2);
+        assert!(loaded_repo.task(2).unwrap().checklist.is_empty());
+    }
+
+    #[test]
+    fn remove_missing_tag_is_a_no_op() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.add_tag(1, String::from("work")).unwrap();
+        let before = repo.task(1).unwrap().clone();
+        repo.remove_tag(1, "home").unwrap();
+        assert_eq!(repo.task(1).unwrap(), &before);
+
+        repo.remove_tag(1, "work").unwrap();
+        assert!(repo.task(1).unwrap().tags.is_empty());
+    }
+
+    #[test]
+    fn purge_done_keeps_unfinished_tasks() {
+        let mut repo = TaskRepository::default();
+        for desc in ["plop", "plap", "plip", "plup"] {
+            repo.new_task(String::from(desc));
+        }
+        repo.set_status(1, TaskStatus::Done).unwrap();
+        repo.set_status(2, TaskStatus::InProgress).unwrap();
+        repo.set_status(4, TaskStatus::Done).unwrap();
+
+        assert_eq!(repo.purge_done(), 2);
+        let ids: Vec<i32> = repo.tasks().map(|task| task.id).collect();
+        assert_eq!(ids, vec![2, 3]);
+        assert_eq!(repo.count_by_status(TaskStatus::Done), 0);
+    }
+
+    #[test]
+    fn ids_in_range_skips_missing_tasks() {
+        let mut repo = TaskRepository::default();
+        for desc in ["Plop", "Plip", "Plap", "Plup"] {
+            repo.new_task(desc.to_string());
+        }
+        repo.delete(2);
+        assert_eq!(repo.ids_in_range(2..=10).collect::<Vec<_>>(), [3, 4]);
+        assert_eq!(repo.ids_in_range(1..=i32::MAX).count(), 3);
+        assert_eq!(repo.ids_in_range(5..=9).next(), None);
+    }
+
+    #[test]
+    fn tags_with_the_same_text_are_shared() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        let mut repo = TaskRepository::default();
+        for desc in ["Plop", "Plip", "Plap"] {
+            repo.new_task(desc.to_string());
+        }
+        repo.add_tag(1, String::from("work")).unwrap();
+        repo.add_tag(2, String::from("work")).unwrap();
+        repo.add_tag(3, String::from("home")).unwrap();
+        let shared = |repo: &TaskRepository, a: i32, b: i32| {
+            let a = repo.task_ref(a).unwrap().tags[0].as_ptr();
+            Some(a) == repo.task_ref(b).map(|task| task.tags[0].as_ptr())
+        };
+        assert!(shared(&repo, 1, 2));
+        assert!(!shared(&repo, 1, 3));
+
+        save_repository(&mut repo, &path).unwrap();
+        let mut loaded = load_repository(&path).unwrap();
+        assert_eq!(loaded, repo);
+        assert!(shared(&loaded, 1, 2));
+        assert_eq!(loaded.task_ref(3).unwrap().tags, vec!["home"]);
+        let json: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["tasks"][0]["tags"], serde_json::json!(["work"]));
+
+        loaded.add_tag(3, String::from("work")).unwrap();
+        let work = loaded.task_ref(1).unwrap().tags[0].as_ptr();
+        assert_eq!(loaded.task_ref(3).unwrap().tags[1].as_ptr(), work);
+        loaded.remove_tag(1, "work").unwrap();
+        assert_eq!(loaded.task_ref(2).unwrap().tags, vec!["work"]);
+    }
+
+    #[test]
+    fn task_ref_finds_existing_tasks_only() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.new_task(String::from("plap"));
+        repo.delete(1);
+
+        assert_eq!(
+            repo.task_ref(2).map(|task| task.description.as_str()),
+            Some("plap")
+        );
+        assert_eq!(repo.task_ref(1), None);
+        assert_eq!(repo.task_ref(3), None);
+    }
+
+    #[test]
+    fn renumber_compacts_sparse_ids() {
+        let mut repo = TaskRepository::default();
+        for (id, desc) in [(9, "plop"), (1, "plap"), (4, "plip")] {
+            repo.new_task_with_id(id, String::from(desc)).unwrap();
+        }
+        repo.set_status(4, TaskStatus::Done).unwrap();
+        repo.add_tag(9, String::from("work")).unwrap();
+        let before: Vec<Task> = repo.tasks().cloned().collect();
+
+        assert_eq!(repo.renumber(), 2);
+        let ids: Vec<i32> = repo.tasks().map(|task| task.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+        for (task, old) in repo.tasks().zip(&before) {
+            assert_eq!(
+                task,
+                &Task {
+                    id: task.id,
+                    ..old.clone()
+                }
+            );
+        }
+        assert_eq!(repo.last_id, 3);
+        repo.new_task(String::from("plup"));
+        assert_eq!(repo.task(4).unwrap().description, "plup");
+        assert_eq!(repo.renumber(), 0);
+    }
+
+    #[test]
+    fn setters_update_task_and_timestamp() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        let before = Local.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        repo.task(1).unwrap().updated_at = before;
+
+        assert_eq!(repo.set_description(1, String::from("plap")), Ok(()));
+        assert_eq!(repo.set_status(1, TaskStatus::Done), Ok(()));
+        assert_eq!(repo.set_priority(1, Priority::High), Ok(()));
+        let task = repo.task(1).unwrap();
+        assert_eq!(task.description, "plap");
+        assert_eq!(task.status, TaskStatus::Done);
+        assert_eq!(task.priority, Priority::High);
+        assert!(task.updated_at > before);
+    }
+
+    #[test]
+    fn setters_reject_unknown_task() {
+        let mut repo = TaskRepository::default();
+        assert_eq!(
+            repo.set_description(1, String::from("plap")),
+            Err(TaskError::NotFound(1))
+        );
+        assert_eq!(
+            repo.set_status(1, TaskStatus::Done),
+            Err(TaskError::NotFound(1))
+        );
+        assert_eq!(
+            repo.set_priority(1, Priority::High),
+            Err(TaskError::NotFound(1))
+        );
+    }
+
+    #[test]
+    fn new_task_with_chosen_id() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        assert_eq!(repo.new_task_with_id(10, String::from("plap")), Ok(()));
+        assert_eq!(repo.task(10).unwrap().description, "plap");
+        assert_eq!(repo.last_id, 10);
+
+        repo.new_task(String::from("plip"));
+        assert_eq!(repo.task(11).unwrap().description, "plip");
+    }
+
+    #[test]
+    fn new_task_with_taken_id() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        assert_eq!(
+            repo.new_task_with_id(1, String::from("plap")),
+            Err(TaskError::Invalid(String::from(
+                "A task with id 1 already exists"
+            )))
+        );
+        assert_eq!(repo.task(1).unwrap().description, "plop");
+    }
 
     #[test]
-    fn repository_save_json() {
+    fn new_task_reuses_freed_id() {
         let mut repo = TaskRepository::default();
+        repo.set_reuse_ids(true);
         repo.new_task(String::from("plop"));
         repo.new_task(String::from("plap"));
-        repo.task(1).status = TaskStatus::Done;
-        let serialized_data = serde_json::to_string(&repo.serializable()).unwrap();
-        let json_object: Value = serde_json::from_str(&serialized_data).unwrap();
+        repo.new_task(String::from("plip"));
+        repo.delete(2);
 
-        assert_eq!(json_object["tasks"][0]["description"], "plop");
-        assert_eq!(json_object["tasks"][0]["status"], "Done");
-        assert_eq!(json_object["tasks"][1]["description"], "plap");
+        repo.new_task(String::from("plup"));
+        assert_eq!(repo.task(2).unwrap().description, "plup");
+        repo.new_task(String::from("plep"));
+        assert_eq!(repo.task(4).unwrap().description, "plep");
+        assert_eq!(repo.task_count(), 4);
+    }
+
+    #[test]
+    fn new_task_reuses_ids_after_load() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        let mut repo = TaskRepository::default();
+        repo.new_task_with_id(2, String::from("plop")).unwrap();
+        repo.new_task_with_id(5, String::from("plap")).unwrap();
+        save_repository(&mut repo, &path).unwrap();
+
+        let mut repo = load_repository(&path).unwrap();
+        repo.set_reuse_ids(true);
+        assert_eq!(repo.new_task_at(String::from("plop"), Local::now()), 1);
+        assert_eq!(repo.new_task_at(String::from("plap"), Local::now()), 3);
+        assert_eq!(repo.new_task_at(String::from("plip"), Local::now()), 4);
+        assert_eq!(repo.new_task_at(String::from("plup"), Local::now()), 6);
+    }
+
+    #[test]
+    fn new_task_without_reuse_counts_up() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.new_task(String::from("plap"));
+        repo.delete(1);
+        repo.new_task(String::from("plip"));
+        assert_eq!(repo.task(3).unwrap().description, "plip");
+    }
+
+    #[test]
+    fn new_task_with_lower_id_keeps_last_id() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.new_task(String::from("plap"));
+        repo.delete(1);
+        assert_eq!(repo.new_task_with_id(1, String::from("plip")), Ok(()));
+        assert_eq!(repo.last_id, 2);
+    }
+
+    #[test]
+    fn reopen_done_task() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.task(1).unwrap().status = TaskStatus::Done;
+
+        assert_eq!(repo.reopen(1), Ok(()));
+        assert_eq!(repo.task(1).unwrap().status, TaskStatus::Todo);
+    }
+
+    #[test]
+    fn completing_task_records_completion_time() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        assert_eq!(repo.task(1).unwrap().completed_at, None);
+
+        let before = Local::now();
+        repo.set_status(1, TaskStatus::Done).unwrap();
+        let completed_at = repo.task(1).unwrap().completed_at.unwrap();
+        assert!(completed_at >= before);
+
+        repo.set_description(1, String::from("plap")).unwrap();
+        repo.set_status(1, TaskStatus::Done).unwrap();
+        assert_eq!(repo.task(1).unwrap().completed_at, Some(completed_at));
+
+        repo.set_status(1, TaskStatus::InProgress).unwrap();
+        assert_eq!(repo.task(1).unwrap().completed_at, None);
+    }
+
+    #[test]
+    fn reopen_clears_completion_time() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.set_status(1, TaskStatus::Done).unwrap();
+
+        repo.reopen(1).unwrap();
+        assert_eq!(repo.task(1).unwrap().completed_at, None);
+    }
+
+    #[test]
+    fn completion_time_round_trip() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.new_task(String::from("plap"));
+        repo.set_status(1, TaskStatus::Done).unwrap();
+        save_repository(&mut repo, &path).unwrap();
+
+        let mut loaded_repo = load_repository(&path).unwrap();
+        assert_eq!(loaded_repo, repo);
+        assert!(loaded_repo.task(1).unwrap().completed_at.is_some());
+        assert_eq!(loaded_repo.task(2).unwrap().completed_at, None);
+    }
+
+    #[test]
+    fn bump_sets_due_date_and_todo() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.set_status(1, TaskStatus::InProgress).unwrap();
+        let due_date = Local.with_ymd_and_hms(2024, 5, 1, 23, 59, 59).unwrap();
+
+        assert_eq!(repo.bump(1, due_date), Ok(()));
+        let task = repo.task(1).unwrap();
+        assert_eq!(task.due_date, Some(due_date));
+        assert_eq!(task.status, TaskStatus::Todo);
+    }
+
+    #[test]
+    fn bump_rejects_done_task() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.set_status(1, TaskStatus::Done).unwrap();
+        let due_date = Local.with_ymd_and_hms(2024, 5, 1, 23, 59, 59).unwrap();
+
+        assert_eq!(
+            repo.bump(1, due_date),
+            Err(TaskError::Invalid(String::from("Task 1 is done")))
+        );
+        assert_eq!(repo.task(1).unwrap().due_date, None);
+        assert_eq!(repo.bump(2, due_date), Err(TaskError::NotFound(2)));
+    }
+
+    #[test]
+    fn reopen_rejects_task_not_done() {
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.task(1).unwrap().status = TaskStatus::InProgress;
+
+        assert_eq!(
+            repo.reopen(1),
+            Err(TaskError::Invalid(String::from("Task 1 is not done")))
+        );
+        assert_eq!(repo.task(1).unwrap().status, TaskStatus::InProgress);
+        assert_eq!(repo.reopen(2), Err(TaskError::NotFound(2)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_through_symlink_keeps_link() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let target = tmp_dir.path().join("tasks.json");
+        let link = tmp_dir.path().join("link.json");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        save_repository(&mut repo, &link).unwrap();
+
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(load_repository(&target).unwrap(), repo);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_symlinks_stops_on_a_loop() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let first = tmp_dir.path().join("first.json");
+        let second = tmp_dir.path().join("second.json");
+        std::os::unix::fs::symlink(&second, &first).unwrap();
+        std::os::unix::fs::symlink(&first, &second).unwrap();
+        let err = resolve_symlinks(&first).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Too many levels of symbolic links"));
+
+        let mut chain = tmp_dir.path().join("tasks.json");
+        for i in 0..MAX_SYMLINK_HOPS {
+            let link = tmp_dir.path().join(format!("link{}.json", i));
+            std::os::unix::fs::symlink(&chain, &link).unwrap();
+            chain = link;
+        }
+        assert_eq!(
+            resolve_symlinks(&chain).unwrap(),
+            tmp_dir.path().join("tasks.json")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_keeps_or_defaults_file_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let reference = tmp_dir.path().join("reference");
+        fs::File::create(&reference).unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        save_repository(&mut repo, &path).unwrap();
+        assert_eq!(mode(&path), mode(&reference));
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        save_repository(&mut repo, &path).unwrap();
+        assert_eq!(mode(&path), 0o640);
+    }
+
+    #[test]
+    fn load_missing_file_gives_empty_repository() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("missing.json");
+        assert_eq!(load_repository(&path), Ok(TaskRepository::default()));
+    }
+
+    #[test]
+    fn round_trip_in_both_layouts() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        for layout in [FileLayout::Wrapped, FileLayout::Array] {
+            let mut repo = TaskRepository::default();
+            repo.new_task(String::from("plop"));
+            repo.new_task(String::from("plap"));
+            repo.set_layout(layout);
+            save_repository(&mut repo, &path).unwrap();
+
+            let content: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+            assert_eq!(content.is_array(), layout == FileLayout::Array);
+            let loaded_repo = load_repository(&path).unwrap();
+            assert_eq!(loaded_repo.layout(), layout);
+            assert_eq!(loaded_repo, repo);
+        }
+    }
+
+    #[test]
+    fn load_detects_layout() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let task = r#"{
+            "id": 3,
+            "description": "plop",
+            "status": "todo",
+            "created_at": "2024-10-16T14:45:18.529270461+02:00",
+            "updated_at": "2024-10-16T14:45:18.529569668+02:00"
+        }"#;
+        for (content, layout) in [
+            (format!("[{}]", task), FileLayout::Array),
+            (format!(r#"{{"tasks": [{}]}}"#, task), FileLayout::Wrapped),
+        ] {
+            let path = tmp_dir.path().join("tasks.json");
+            fs::write(&path, content).unwrap();
+            let mut repo = load_repository(&path).unwrap();
+            assert_eq!(repo.layout(), layout);
+            assert_eq!(repo.task(3).unwrap().description, "plop");
+        }
+    }
+
+    #[test]
+    fn load_corrupt_file_is_an_error() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        fs::write(&path, "{\"tasks\": [{\"id\": 1,").unwrap();
+
+        let err = load_repository(&path).unwrap_err();
+        assert!(matches!(err, TaskError::Parse(_)));
+        assert!(err.to_string().starts_with("Failed to parse task file: "));
+    }
+
+    #[test]
+    fn save_to_unwritable_path_is_an_error() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("missing_dir").join("tasks.json");
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+
+        assert!(save_repository(&mut repo, &path).is_err());
+        assert!(!fs::exists(&path).unwrap());
+    }
+
+    #[test]
+    fn count_tasks_on_large_file() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        assert_eq!(count_tasks(&path), Ok(0));
+
+        let mut repo = TaskRepository::default();
+        for i in 0..5000 {
+            repo.new_task(format!("task {}", i));
+        }
+        save_repository(&mut repo, &path).unwrap();
+        assert_eq!(count_tasks(&path), Ok(5000));
+        repo.set_layout(FileLayout::Array);
+        save_repository(&mut repo, &path).unwrap();
+        assert_eq!(count_tasks(&path), Ok(5000));
+
+        fs::write(&path, r#"{"tasks": 3}"#).unwrap();
+        assert!(matches!(count_tasks(&path), Err(TaskError::Parse(_))));
+        fs::write(&path, r#"[{"description": "no id"}]"#).unwrap();
+        assert!(matches!(count_tasks(&path), Err(TaskError::Parse(_))));
+    }
+
+    #[test]
+    fn count_tasks_rejects_duplicate_ids() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        let tasks = r#"[{"id": 3, "x": [1]}, {"id": 1}, {"id": 3}, {"id": 2}, {"id": 1}]"#;
+        for content in [tasks.to_string(), format!(r#"{{"tasks": {}}}"#, tasks)] {
+            fs::write(&path, content).unwrap();
+            assert_eq!(
+                count_tasks(&path),
+                Err(TaskError::Invalid(
+                    "Several tasks have the same id: 1, 3".to_string()
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn restore_backup_undoes_last_save() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        save_repository(&mut repo, &path).unwrap();
+        let saved_repo = repo.clone();
+        repo.delete(1);
+        save_repository(&mut repo, &path).unwrap();
+        assert_eq!(load_repository(&path).unwrap().task_count(), 0);
+
+        let mut repo = load_repository(&path).unwrap();
+        assert_eq!(restore_backup(&mut repo, &path), Ok(()));
+        save_repository(&mut repo, &path).unwrap();
+        let mut loaded_repo = load_repository(&path).unwrap();
+        assert_eq!(loaded_repo, saved_repo);
+        assert_eq!(loaded_repo.task(1).unwrap().description, "plop");
+        assert_eq!(
+            load_repository(&backup_path(&path)).unwrap().task_count(),
+            0
+        );
+    }
+
+    #[test]
+    fn unchanged_save_keeps_backup() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("keep"));
+        repo.new_task(String::from("victim"));
+        save_repository(&mut repo, &path).unwrap();
+        repo.delete(2);
+        save_repository(&mut repo, &path).unwrap();
+        save_repository(&mut repo, &path).unwrap();
+
+        let mut repo = load_repository(&path).unwrap();
+        assert_eq!(restore_backup(&mut repo, &path), Ok(()));
+        assert_eq!(repo.task(2).unwrap().description, "victim");
+    }
+
+    #[test]
+    fn restore_backup_keeps_layout() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        let mut repo = TaskRepository::default();
+        save_repository(&mut repo, &path).unwrap();
+        repo.new_task(String::from("plop"));
+        repo.set_layout(FileLayout::Array);
+        save_repository(&mut repo, &path).unwrap();
+
+        assert_eq!(restore_backup(&mut repo, &path), Ok(()));
+        assert_eq!(repo.task_count(), 0);
+        assert_eq!(repo.layout(), FileLayout::Array);
+    }
+
+    #[test]
+    fn restore_without_backup_is_an_error() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        save_repository(&mut repo, &path).unwrap();
+        assert!(!fs::exists(backup_path(&path)).unwrap());
+
+        assert_eq!(
+            restore_backup(&mut repo, &path),
+            Err(TaskError::Invalid("Nothing to undo".to_string()))
+        );
+        assert_eq!(repo.task_count(), 1);
+    }
+
+    #[test]
+    fn dump_then_restore_gives_an_equal_repository() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("dump.json");
+        let mut repo = TaskRepository::default();
+        for desc in ["plop", "plap", "plip"] {
+            repo.new_task(String::from(desc));
+        }
+        repo.set_status(2, TaskStatus::Done).unwrap();
+        repo.add_tag(3, String::from("work")).unwrap();
+        repo.add_checklist_item(3, String::from("first")).unwrap();
+        dump_repository(&repo, fs::File::create(&path).unwrap()).unwrap();
+
+        let mut restored = TaskRepository::default();
+        restored.new_task(String::from("replaced"));
+        assert_eq!(restore_dump(&mut restored, &path), Ok(3));
+        assert_eq!(restored, repo);
+    }
+
+    #[test]
+    fn restore_dump_rejects_malformed_file() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("dump.json");
+        fs::write(&path, r#"{"tasks": [{"id": 1}]"#).unwrap();
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+
+        let err = restore_dump(&mut repo, &path).unwrap_err();
+        assert!(matches!(err, TaskError::Parse(_)), "{}", err);
+        assert_eq!(repo.task(1).unwrap().description, "plop");
+
+        let missing = tmp_dir.path().join("missing.json");
+        assert_eq!(
+            restore_dump(&mut repo, &missing),
+            Err(TaskError::Invalid(format!(
+                "Dump file {} does not exist",
+                missing.display()
+            )))
+        );
+    }
+
+    #[test]
+    fn second_lock_fails_until_first_is_released() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        let lock_path = tmp_dir.path().join("tasks.json.lock");
+        let lock = lock_file(&path).unwrap();
+        assert!(fs::exists(&lock_path).unwrap());
+
+        assert_eq!(
+            lock_file(&path).unwrap_err().to_string(),
+            format!("Task file {} is locked by another process", path.display())
+        );
+
+        drop(lock);
+        assert!(lock_file(&path).is_ok());
+    }
+
+    #[test]
+    fn lock_file_left_by_a_dead_process_does_not_lock() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        let lock_path = tmp_dir.path().join("tasks.json.lock");
+        // What a process killed while holding the lock leaves behind, or an older version
+        // of tasklist that locked by creating the file.
+        fs::write(&lock_path, "").unwrap();
+
+        let lock = lock_file(&path).unwrap();
+        assert!(lock_file(&path).is_err());
+        drop(lock);
+        assert!(lock_file(&path).is_ok());
+    }
+
+    #[test]
+    fn file_version_changes_with_content() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        assert_eq!(file_version(&path), Ok(FileVersion::default()));
+
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        let saved = save_repository(&mut repo, &path).unwrap();
+        assert_eq!(file_version(&path), Ok(saved));
+        assert_eq!(load_repository_with_version(&path).unwrap().1, saved);
+
+        repo.set_layout(FileLayout::Array);
+        let resaved = save_repository(&mut repo, &path).unwrap();
+        assert_ne!(resaved, saved);
+        assert_eq!(file_version(&path), Ok(resaved));
+    }
+
+    #[test]
+    fn locked_file_keeps_content() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        save_repository(&mut repo, &path).unwrap();
+        let content = fs::read(&path).unwrap();
+
+        let _lock = lock_file(&path).unwrap();
+        assert!(lock_file(&path).is_err());
+        assert_eq!(fs::read(&path).unwrap(), content);
+    }
+
+    #[test]
+    fn failed_save_keeps_previous_content() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        save_repository(&mut repo, &path).unwrap();
+        let previous_content = fs::read(&path).unwrap();
+
+        let result = write_atomically(&path, |file| {
+            file.write_all(b"{\"tasks\": [")?;
+            Err(std::io::Error::other("disk full"))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(fs::read(&path).unwrap(), previous_content);
+        assert_eq!(fs::read_dir(tmp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn load_save_round_trip_keeps_timestamps() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.new_task_at(
+            String::from("plap"),
+            Local.with_ymd_and_hms(2024, 3, 6, 1, 2, 3).unwrap(),
+        );
+        repo.task(1).unwrap().updated_at = Local::now();
+        save_repository(&mut repo, &path).unwrap();
+        let saved_content = fs::read_to_string(&path).unwrap();
+
+        let mut loaded_repo = load_repository(&path).unwrap();
+        save_repository(&mut loaded_repo, &path).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), saved_content);
+    }
+
+    #[test]
+    fn json_schema_describes_saved_tasks() {
+        let schema = json_schema();
+        assert_eq!(schema["properties"]["tasks"]["type"], "array");
+        let task_schema = &schema["$defs"]["task"];
+        assert!(task_schema["required"]
+            .as_array()
+            .unwrap()
+            .contains(&Value::from("id")));
+
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        let saved = serde_json::to_value(repo.serializable()).unwrap();
+        let properties = task_schema["properties"].as_object().unwrap();
+        for field in saved["tasks"][0].as_object().unwrap().keys() {
+            assert!(
+                properties.contains_key(field),
+                "{} is not in the schema",
+                field
+            );
+        }
+    }
+
+    /// Environment variable telling `load_keeps_instants_in_any_timezone` it runs as the child
+    /// process with a non-default `TZ`.
+    const TZ_CHILD_VAR: &str = "TASKLIST_TZ_TEST_CHILD";
+
+    #[test]
+    fn load_keeps_instants_in_any_timezone() {
+        if env::var_os(TZ_CHILD_VAR).is_none() {
+            // The local timezone is process-wide, so the check runs in a child process
+            // instead of changing it under the other tests.
+            for tz in ["Pacific/Kiritimati", "America/St_Johns", "UTC"] {
+                let status = std::process::Command::new(env::current_exe().unwrap())
+                    .args([
+                        "--exact",
+                        "task_repository::tests::load_keeps_instants_in_any_timezone",
+                    ])
+                    .env("TZ", tz)
+                    .env(TZ_CHILD_VAR, "1")
+                    .status()
+                    .unwrap();
+                assert!(status.success(), "loading failed under TZ={}", tz);
+            }
+            return;
+        }
+
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        fs::write(
+            &path,
+            r#"{"tasks": [{
+                "id": 1,
+                "description": "plop",
+                "status": "todo",
+                "created_at": "2024-10-16T14:45:18.529270461+02:00",
+                "updated_at": "2024-10-16T23:30:00-03:30"
+            }]}"#,
+        )
+        .unwrap();
+        let mut repo = load_repository(&path).unwrap();
+        let task = repo.task(1).unwrap();
+        assert_eq!(
+            task.created_at,
+            DateTime::parse_from_rfc3339("2024-10-16T12:45:18.529270461Z").unwrap()
+        );
+        assert_eq!(
+            task.updated_at,
+            DateTime::parse_from_rfc3339("2024-10-17T03:00:00Z").unwrap()
+        );
     }
 
     #[test]
     fn repository_load_json() {
-        let expected = HashMap::from([
+        let created_at = DateTime::parse_from_rfc3339("2024-10-16T14:45:18.529270461+02:00")
+            .unwrap()
+            .with_timezone(&Local);
+        let updated_at = DateTime::parse_from_rfc3339("2024-10-16T14:45:18.529569668+02:00")
+            .unwrap()
+            .with_timezone(&Local);
+        let expected = BTreeMap::from([
             (
                 0,
                 Task {
                     id: 0,
                     description: String::from("plop"),
                     status: TaskStatus::Todo,
-                    created_at: DateTime::from(
-                        Local.with_ymd_and_hms(2024, 01, 01, 01, 02, 03).unwrap(),
-                    ),
-                    updated_at: DateTime::from(
-                        Local.with_ymd_and_hms(2024, 02, 01, 05, 02, 03).unwrap(),
-                    ),
+                    priority: Priority::Medium,
+                    created_at,
+                    updated_at,
+                    due_date: None,
+                    tags: Vec::new(),
+                    completed_at: None,
+                    checklist: Vec::new(),
                 },
             ),
             (
@@ -235,46 +2922,154 @@ mod tests {
                     id: 1,
                     description: String::from("plap"),
                     status: TaskStatus::Done,
-                    created_at: DateTime::from(
-                        Local.with_ymd_and_hms(2024, 03, 06, 01, 02, 03).unwrap(),
-                    ),
-                    updated_at: DateTime::from(
-                        Local.with_ymd_and_hms(2024, 02, 01, 05, 12, 03).unwrap(),
-                    ),
+                    priority: Priority::Medium,
+                    created_at,
+                    updated_at,
+                    due_date: None,
+                    tags: Vec::new(),
+                    completed_at: None,
+                    checklist: Vec::new(),
                 },
             ),
         ]);
-        let content = format!(
-            "\
-        {{\
+        let content = "\
+        {\
         \"tasks\": [\
-            {{\
+            {\
                 \"id\": 0,\
                 \"description\": \"plop\",\
                 \"status\": \"Todo\",\
                 \"created_at\":\"2024-10-16T14:45:18.529270461+02:00\",\
                 \"updated_at\":\"2024-10-16T14:45:18.529569668+02:00\"
-            }},\
-            {{\
+            },\
+            {\
                 \"id\": 1,\
                 \"description\": \"plap\",\
                 \"status\": \"Done\",\
                 \"created_at\":\"2024-10-16T14:45:18.529270461+02:00\",\
                 \"updated_at\":\"2024-10-16T14:45:18.529569668+02:00\"
-            }}\
+            }\
         ]\
-        }}\
+        }\
         "
-        );
+        .to_string();
         let object: TaskRepositoryForSerialization = serde_json::from_str(&content).unwrap();
-        let repo = TaskRepository::from_serialization(object);
+        let repo = from_serialization(object).unwrap();
 
-        //Can't assert_eq because repo because of dates
-        assert_eq!(repo.tasks.len(), expected.len());
-        for (key, value) in repo.tasks.iter() {
-            assert_eq!(value.id, expected[key].id);
-            assert_eq!(value.description, expected[key].description);
-            assert_eq!(value.status, expected[key].status);
+        assert_eq!(repo.tasks, expected);
+        assert_eq!(repo.last_id, 1);
+    }
+
+    #[test]
+    fn streaming_load_equals_batch_load() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        let mut repo = TaskRepository::default();
+        for i in 0..200 {
+            repo.new_task(format!("task {}", i));
+        }
+        repo.delete(200);
+        repo.set_status(3, TaskStatus::Done).unwrap();
+        repo.add_tag(5, String::from("work")).unwrap();
+        for layout in [FileLayout::Wrapped, FileLayout::Array] {
+            repo.set_layout(layout);
+            save_repository(&mut repo, &path).unwrap();
+            let content = fs::read_to_string(&path).unwrap();
+            let object = match layout {
+                FileLayout::Wrapped => serde_json::from_str(&content).unwrap(),
+                FileLayout::Array => TaskRepositoryForSerialization {
+                    tasks: serde_json::from_str(&content).unwrap(),
+                },
+            };
+            let mut batch_repo = from_serialization(object).unwrap();
+            batch_repo.set_layout(layout);
+            assert_eq!(load_repository(&path), Ok(batch_repo));
+        }
+    }
+
+    #[test]
+    fn from_serialization_reports_duplicate_ids() {
+        let task = |id: i32, description: &str| {
+            let mut repo = TaskRepository::default();
+            repo.new_task_with_id(id, String::from(description))
+                .unwrap();
+            repo.task(id).unwrap().clone()
+        };
+        let object = TaskRepositoryForSerialization {
+            tasks: vec![task(1, "plop"), task(2, "plap"), task(1, "plip")],
+        };
+        assert_eq!(
+            from_serialization(object),
+            Err(TaskError::Invalid(
+                "Several tasks have the same id: 1".to_string()
+            ))
+        );
+
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        let tasks = vec![task(3, "a"), task(1, "b"), task(3, "c"), task(1, "d")];
+        fs::write(&path, serde_json::to_string(&tasks).unwrap()).unwrap();
+        assert_eq!(
+            load_repository(&path),
+            Err(TaskError::Invalid(
+                "Several tasks have the same id: 1, 3".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn load_file_without_priority_defaults_to_medium() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        fs::write(
+            &path,
+            r#"{"tasks": [{
+                "id": 1,
+                "description": "plop",
+                "status": "Todo",
+                "created_at": "2024-10-16T14:45:18.529270461+02:00",
+                "updated_at": "2024-10-16T14:45:18.529569668+02:00"
+            }]}"#,
+        )
+        .unwrap();
+
+        let mut repo = load_repository(&path).unwrap();
+        assert_eq!(repo.task(1).unwrap().priority, Priority::Medium);
+        assert_eq!(repo.task(1).unwrap().due_date, None);
+    }
+
+    #[test]
+    fn due_date_round_trip() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("tasks.json");
+        let due_date = Local.with_ymd_and_hms(2024, 5, 1, 23, 59, 59).unwrap();
+        let mut repo = TaskRepository::default();
+        repo.new_task(String::from("plop"));
+        repo.new_task(String::from("plap"));
+        repo.set_due_date(1, Some(due_date)).unwrap();
+        save_repository(&mut repo, &path).unwrap();
+
+        let mut loaded_repo = load_repository(&path).unwrap();
+        assert_eq!(loaded_repo.task(1).unwrap().due_date, Some(due_date));
+        assert_eq!(loaded_repo.task(2).unwrap().due_date, None);
+    }
+
+    #[test]
+    fn overdue_skips_done_and_future_tasks() {
+        let now = Local::now();
+        let mut repo = TaskRepository::default();
+        for desc in ["late", "late but done", "on time", "no due date"] {
+            repo.new_task(String::from(desc));
         }
+        repo.set_due_date(1, Some(now - TimeDelta::days(1)))
+            .unwrap();
+        repo.set_due_date(2, Some(now - TimeDelta::days(1)))
+            .unwrap();
+        repo.set_status(2, TaskStatus::Done).unwrap();
+        repo.set_due_date(3, Some(now + TimeDelta::days(1)))
+            .unwrap();
+
+        let ids: Vec<i32> = repo.overdue(now).iter().map(|task| task.id).collect();
+        assert_eq!(ids, vec![1]);
     }
 }